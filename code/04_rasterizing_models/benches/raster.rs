@@ -0,0 +1,244 @@
+// Frame times for the viewer's scene, rendered offscreen:
+//   cargo bench --bench raster [path/to/scene.gltf] [frames]
+// Defaults to the damaged helmet from glTF-Sample-Models, the same file main.rs
+// loads. Without it two spheres stand in, a dense one with triangles of a few
+// pixels and a coarse one with large triangles, so the numbers are only comparable
+// between runs on the same scene (it gets printed first).
+//
+// "flat" is a fragment shader returning a constant, most of its time is the raster
+// loop itself; "pbr" is what the viewer draws. "flat per pixel" is the same frame
+// through the old inner loop, as a baseline for the edge stepping and block rejection
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+use ruster::*;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 500;
+const HEIGHT: usize = 500;
+
+// The raster loop from before edge stepping: every pixel of the bounding box gets
+// its barycentric coordinates from scratch. Kept here only to compare against
+fn raster_mesh_per_pixel<VS, F>(
+    mesh: &Mesh,
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+) where
+    VS: VertexShader<Vertex>,
+    F: FragmentShader<VS::Varyings>,
+{
+    let depth_state = &settings.depth;
+    let viewport_size = target.size();
+    let shaded: Vec<_> = mesh
+        .vertices()
+        .iter()
+        .map(|vertex| vertex_shader.shade_vertex(vertex))
+        .collect();
+    for triangle in mesh.triangles() {
+        let clip_tri = Triangle {
+            v0: shaded[triangle.x as usize],
+            v1: shaded[triangle.y as usize],
+            v2: shaded[triangle.z as usize],
+        };
+        setup_clip_space_triangle(&clip_tri, settings, viewport_size, |setup| {
+            let bb = &setup.bounding_box;
+            let [sc0, sc1, sc2] = setup.screen;
+            let [rec0, rec1, rec2] = setup.rec_w;
+            let [z0, z1, z2] = setup.depth.map(|z| depth_state.map_to_range(z));
+            let [v0, v1, v2] = setup.varyings;
+            for y in bb.top as usize..=bb.bottom as usize {
+                for x in bb.left as usize..=bb.right as usize {
+                    let coords = glam::vec2(x as f32, y as f32) + 0.5;
+                    let pixel_id = coords_to_index(x, y, target.width);
+                    let area = edge_function(sc0, sc1, sc2);
+                    let bary = match barycentric_coordinates(coords, sc0, sc1, sc2, area) {
+                        Some(bary) => bary,
+                        None => continue,
+                    };
+                    let depth = bary.x * z0 + bary.y * z1 + bary.z * z2;
+                    if !depth_state.compare.test(depth, target.depth[pixel_id]) {
+                        continue;
+                    }
+                    let correction = 1.0 / (bary.x * rec0 + bary.y * rec1 + bary.z * rec2);
+                    let varyings = (v0 * bary.x + v1 * bary.y + v2 * bary.z) * correction;
+                    // no quads, so no derivatives either
+                    let fragment = Fragment {
+                        coords,
+                        depth,
+                        varyings,
+                        ddx: varyings * 0.0,
+                        ddy: varyings * 0.0,
+                    };
+                    if let Some(color) = fragment_shader.shade_fragment(&fragment) {
+                        target.depth[pixel_id] = depth;
+                        target.color[pixel_id] = color;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn time_frames(frames: usize, mut render: impl FnMut(&mut RenderTarget)) -> (Duration, Duration) {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    // warm up caches and the thread pool
+    for _ in 0..2 {
+        target.clear(Vec4::ZERO);
+        render(&mut target);
+    }
+    let mut times = Vec::with_capacity(frames);
+    for _ in 0..frames {
+        target.clear(Vec4::ZERO);
+        let start = Instant::now();
+        render(&mut target);
+        times.push(start.elapsed());
+    }
+    times.sort();
+    (times[0], times[times.len() / 2])
+}
+
+fn main() {
+    // cargo bench passes --bench, skip flags
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    let path = args
+        .first()
+        .map(String::as_str)
+        .unwrap_or("../../assets/damagedhelmet/damagedhelmet.gltf");
+    let frames = args
+        .get(1)
+        .and_then(|frames| frames.parse().ok())
+        .unwrap_or(20);
+
+    match load_gltf_scene(Path::new(path)) {
+        Ok(scene) => {
+            println!("scene: {}", path);
+            bench_scene(&scene.models, frames);
+        }
+        Err(error) => {
+            eprintln!("can't load {}: {}", path, error);
+            for (segments, rings) in [(256, 128), (16, 8)] {
+                println!("scene: sphere({}, {})", segments, rings);
+                let sphere = Model {
+                    mesh: common::sphere(segments, rings),
+                    ..Default::default()
+                };
+                bench_scene(&[sphere], frames);
+            }
+        }
+    }
+}
+
+fn bench_scene(models: &[Model], frames: usize) {
+    let triangles: usize = models
+        .iter()
+        .map(|model| model.mesh.triangles().len())
+        .sum();
+    println!(
+        "{} triangles, {}x{}, {} frames",
+        triangles, WIDTH, HEIGHT, frames
+    );
+
+    // same view as the viewer's first frame
+    let camera = Camera {
+        aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 8.0)),
+        frustum_near: 4.0,
+        frustum_far: 100.0,
+        ..Default::default()
+    };
+    let parent = Mat4::from_rotation_x(std::f32::consts::FRAC_PI_4);
+    let view_proj = camera.projection() * camera.view();
+    let eye = camera.transform.translation;
+    let lights = [Light::Directional {
+        direction: -Vec3::ONE.normalize(),
+        color: Vec3::ONE,
+        intensity: std::f32::consts::PI,
+    }];
+    let settings = RenderSettings::default();
+
+    let flat_vertex = |model: &Model| {
+        let mvp = view_proj * parent * model.transform;
+        move |vertex: &Vertex| ClipVertex::new(mvp * vertex.position.xyz().extend(1.0), 0.0)
+    };
+    let flat_fragment = |_: &Fragment<f32>| Some(Vec4::ONE);
+
+    let report = |name: &str, (min, median): (Duration, Duration)| {
+        println!(
+            "{:<14} min {:>8.2} ms   median {:>8.2} ms",
+            name,
+            min.as_secs_f64() * 1000.0,
+            median.as_secs_f64() * 1000.0
+        );
+    };
+    report(
+        "flat",
+        time_frames(frames, |target| {
+            for model in models {
+                raster_mesh(
+                    &model.mesh,
+                    &flat_vertex(model),
+                    &flat_fragment,
+                    &settings,
+                    target,
+                );
+            }
+        }),
+    );
+    report(
+        "flat per pixel",
+        time_frames(frames, |target| {
+            for model in models {
+                raster_mesh_per_pixel(
+                    &model.mesh,
+                    &flat_vertex(model),
+                    &flat_fragment,
+                    &settings,
+                    target,
+                );
+            }
+        }),
+    );
+    report(
+        "flat tiled",
+        time_frames(frames, |target| {
+            for model in models {
+                raster_mesh_tiled(
+                    &model.mesh,
+                    &flat_vertex(model),
+                    &flat_fragment,
+                    &settings,
+                    target,
+                );
+            }
+        }),
+    );
+    report(
+        "pbr",
+        time_frames(frames, |target| {
+            for model in models {
+                let model_matrix = parent * model.transform;
+                let shader =
+                    PbrShader::new(&model_matrix, &view_proj, eye, &model.material, &lights);
+                raster_mesh(&model.mesh, &shader, &shader, &settings, target);
+            }
+        }),
+    );
+    report(
+        "pbr tiled",
+        time_frames(frames, |target| {
+            for model in models {
+                let model_matrix = parent * model.transform;
+                let shader =
+                    PbrShader::new(&model_matrix, &view_proj, eye, &model.material, &lights);
+                raster_mesh_tiled(&model.mesh, &shader, &shader, &settings, target);
+            }
+        }),
+    );
+}
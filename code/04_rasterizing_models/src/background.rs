@@ -0,0 +1,127 @@
+use crate::{
+    environment::{sample_equirect, EnvironmentMap},
+    render_target::RenderTarget,
+    sampler::{AddressMode, Filter, Sampler},
+    texture::{Texture, TextureError},
+    utils::coords_to_index,
+};
+use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use std::path::Path;
+
+// Six square images, one per side of a cube around the camera, in the order
+// OpenGL and KTX use: +X, -X, +Y, -Y, +Z, -Z
+pub struct CubeMap {
+    pub faces: [Texture; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [Texture; 6]) -> Self {
+        Self { faces }
+    }
+
+    // same order as the faces
+    pub fn load(paths: [&Path; 6]) -> Result<Self, TextureError> {
+        let [px, nx, py, ny, pz, nz] = paths;
+        Ok(Self::new([
+            Texture::load(px)?,
+            Texture::load(nx)?,
+            Texture::load(py)?,
+            Texture::load(ny)?,
+            Texture::load(pz)?,
+            Texture::load(nz)?,
+        ]))
+    }
+
+    // Which face a direction hits and where, by its largest component. The uv
+    // orientation of every face is the one from the OpenGL spec, with v going down
+    // the image
+    pub fn face_uv(direction: Vec3) -> (usize, Vec2) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (face, major, s, t) = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+            if x > 0.0 {
+                (0, x, -z, -y)
+            } else {
+                (1, -x, z, -y)
+            }
+        } else if y.abs() >= z.abs() {
+            if y > 0.0 {
+                (2, y, x, z)
+            } else {
+                (3, -y, x, -z)
+            }
+        } else if z > 0.0 {
+            (4, z, x, -y)
+        } else {
+            (5, -z, -x, -y)
+        };
+        let major = major.max(f32::MIN_POSITIVE);
+        (face, glam::vec2(s / major, t / major) * 0.5 + 0.5)
+    }
+
+    // linear rgba, full resolution
+    pub fn sample(&self, direction: Vec3) -> Vec4 {
+        let (face, uv) = Self::face_uv(direction);
+        // faces don't know about each other, the seams get clamped
+        Sampler::new(Filter::Bilinear)
+            .with_address_mode(AddressMode::ClampToEdge, AddressMode::ClampToEdge)
+            .sample_level(&self.faces[face], 0.0, uv)
+    }
+}
+
+// What ends up wherever the scene didn't draw anything
+pub enum Background<'a> {
+    Color(Vec3),
+    // latitude-longitude image, see environment::equirect_uv
+    Equirect(&'a Texture),
+    CubeMap(&'a CubeMap),
+}
+
+impl<'a> Background<'a> {
+    // the image the environment lighting comes from
+    pub fn from_environment(environment: &'a EnvironmentMap) -> Self {
+        Background::Equirect(&environment.source)
+    }
+
+    // linear rgb seen looking towards `direction`
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Background::Color(color) => *color,
+            Background::Equirect(texture) => sample_equirect(texture, direction),
+            Background::CubeMap(cube_map) => cube_map.sample(direction).xyz(),
+        }
+    }
+
+    // Fills every pixel whose depth is still `clear_depth` (f32::INFINITY after
+    // RenderTarget::clear, 0 with reversed-Z) with what lies behind it, infinitely
+    // far away. Goes after the opaque surfaces and before the transparent ones,
+    // which don't write depth. `view_proj` is the camera's projection * view
+    pub fn draw(&self, view_proj: &Mat4, clear_depth: f32, target: &mut RenderTarget) {
+        let inverse_view_proj = view_proj.inverse();
+        let size = target.size();
+        for y in 0..target.height {
+            for x in 0..target.width {
+                let id = coords_to_index(x, y, target.width);
+                if target.depth[id] != clear_depth {
+                    continue;
+                }
+                // pixel center, the other way around from TriangleSetup
+                let ndc = glam::vec2(
+                    (x as f32 + 0.5) / size.x * 2.0 - 1.0,
+                    1.0 - (y as f32 + 0.5) / size.y * 2.0,
+                );
+                let direction = view_direction(&inverse_view_proj, ndc);
+                target.color[id] = self.radiance(direction).extend(1.0);
+            }
+        }
+    }
+}
+
+// The direction from the eye through a point on the screen: two points along the
+// pixel's ray, unprojected. Their w is 1 over their distance from the eye, whichever
+// way the depth goes
+pub fn view_direction(inverse_view_proj: &Mat4, ndc: Vec2) -> Vec3 {
+    let a = *inverse_view_proj * ndc.extend(0.25).extend(1.0);
+    let b = *inverse_view_proj * ndc.extend(0.75).extend(1.0);
+    let (near, far) = if a.w >= b.w { (a, b) } else { (b, a) };
+    (far.xyz() / far.w - near.xyz() / near.w).normalize_or_zero()
+}
@@ -0,0 +1,99 @@
+use crate::utils::lerp;
+use glam::Vec3;
+use std::f32::consts::PI;
+
+// The metallic-roughness BRDF from the glTF spec (appendix B): Lambert diffuse plus
+// Cook-Torrance specular with the GGX distribution, the height correlated Smith
+// visibility and Schlick's Fresnel. Every vector is normalized, n is the surface
+// normal, v points to the eye and l to the light
+
+// reflectance at normal incidence of every dielectric, more or less
+pub const DIELECTRIC_F0: f32 = 0.04;
+
+// clamped so perfectly smooth surfaces don't turn the highlight into a single
+// infinitely bright point
+pub fn alpha_from_roughness(roughness: f32) -> f32 {
+    let roughness = roughness.clamp(0.03, 1.0);
+    roughness * roughness
+}
+
+// Trowbridge-Reitz (GGX) normal distribution: how many microfacets face h.
+// alpha is the perceptual roughness squared
+pub fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+// Smith's masking-shadowing divided by the 4 n.l n.v of the specular denominator
+pub fn visibility_smith_ggx(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let ggx_v = n_dot_l * (n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2).sqrt();
+    let ggx_l = n_dot_v * (n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2).sqrt();
+    let sum = ggx_v + ggx_l;
+    if sum > 0.0 {
+        0.5 / sum
+    } else {
+        0.0
+    }
+}
+
+// How much light gets reflected instead of refracted, f0 straight on and 1 at
+// grazing angles
+pub fn fresnel_schlick(f0: Vec3, v_dot_h: f32) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - v_dot_h.abs()).clamp(0.0, 1.0).powi(5)
+}
+
+// A surface as the BRDF sees it, see Material
+#[derive(Debug, Copy, Clone)]
+pub struct SurfaceParams {
+    // linear rgb
+    pub base_color: Vec3,
+    pub metallic: f32,
+    // perceptual, gets squared
+    pub roughness: f32,
+}
+
+impl SurfaceParams {
+    // metals have no diffuse, their base color tints the reflection instead
+    pub fn diffuse_color(&self) -> Vec3 {
+        lerp(self.base_color, Vec3::ZERO, self.metallic)
+    }
+
+    pub fn f0(&self) -> Vec3 {
+        lerp(Vec3::splat(DIELECTRIC_F0), self.base_color, self.metallic)
+    }
+
+    // see alpha_from_roughness
+    pub fn alpha(&self) -> f32 {
+        alpha_from_roughness(self.roughness)
+    }
+
+    // The BRDF, light reflected towards v for every unit of light coming from l
+    pub fn brdf(&self, n: Vec3, v: Vec3, l: Vec3) -> Vec3 {
+        let n_dot_l = n.dot(l);
+        // seen from below (e.g. interpolated normals at the silhouette), as if
+        // seen from the edge
+        let n_dot_v = n.dot(v).max(1e-4);
+        if n_dot_l <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let h = (l + v).normalize_or_zero();
+        let n_dot_h = n.dot(h).max(0.0);
+        let v_dot_h = v.dot(h);
+
+        let alpha = self.alpha();
+        let fresnel = fresnel_schlick(self.f0(), v_dot_h);
+        let diffuse = (Vec3::ONE - fresnel) * self.diffuse_color() / PI;
+        let specular = fresnel
+            * distribution_ggx(n_dot_h, alpha)
+            * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+        diffuse + specular
+    }
+
+    // light reflected towards v by a light from l, `radiance` is the light's color
+    // times its intensity
+    pub fn shade(&self, n: Vec3, v: Vec3, l: Vec3, radiance: Vec3) -> Vec3 {
+        self.brdf(n, v, l) * radiance * n.dot(l).max(0.0)
+    }
+}
@@ -9,6 +9,8 @@ pub struct Camera {
     pub aspect_ratio: f32,
     pub transform: Transform,
     pub speed: f32,
+    // near ends up at depth 1 and far at 0, see DepthState::reversed_z
+    pub reversed_z: bool,
 }
 
 impl Default for Camera {
@@ -20,18 +22,20 @@ impl Default for Camera {
             aspect_ratio: 1.0,
             transform: Transform::IDENTITY,
             speed: 1.0,
+            reversed_z: false,
         }
     }
 }
 
 impl Camera {
     pub fn projection(&self) -> Mat4 {
-        Mat4::perspective_rh(
-            self.fov,
-            self.aspect_ratio,
-            self.frustum_near,
-            self.frustum_far,
-        )
+        // swapping the planes is all it takes to flip the depth range
+        let (near, far) = if self.reversed_z {
+            (self.frustum_far, self.frustum_near)
+        } else {
+            (self.frustum_near, self.frustum_far)
+        };
+        Mat4::perspective_rh(self.fov, self.aspect_ratio, near, far)
     }
 
     pub fn view(&self) -> Mat4 {
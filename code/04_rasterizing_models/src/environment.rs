@@ -0,0 +1,315 @@
+use crate::{
+    brdf::{alpha_from_roughness, distribution_ggx, visibility_smith_ggx, SurfaceParams},
+    sampler::{AddressMode, Filter, Sampler},
+    texture::Texture,
+    utils::{coords_to_index, lerp},
+};
+use glam::{Vec2, Vec3, Vec4Swizzles};
+use std::f32::consts::PI;
+
+// Image based lighting: the light coming from every direction, taken from an
+// equirectangular (latitude-longitude) image, usually HDR. Everything that doesn't
+// depend on the surface gets precomputed:
+// - diffuse: the irradiance around every normal, as spherical harmonics
+// - specular: the image blurred by GGX at a few roughnesses, one image each, plus
+//   a lookup table with the rest of the split sum (see BrdfLut)
+
+// roughness 0, 0.2, ... 1
+pub const SPECULAR_LEVELS: usize = 6;
+// samples per texel when prefiltering
+const PREFILTER_SAMPLES: u32 = 64;
+
+// The equirectangular mapping: u goes around the Y axis starting from +Z, with -Z
+// (straight ahead for a camera) in the middle of the image, v from +Y (top row) to -Y
+pub fn equirect_uv(direction: Vec3) -> Vec2 {
+    let d = direction.normalize_or_zero();
+    let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+    glam::vec2(u, v)
+}
+
+pub fn equirect_direction(uv: Vec2) -> Vec3 {
+    let phi = (uv.x - 0.5) * 2.0 * PI;
+    let theta = uv.y * PI;
+    glam::vec3(
+        phi.sin() * theta.sin(),
+        theta.cos(),
+        -phi.cos() * theta.sin(),
+    )
+}
+
+// how equirectangular images get read: around the horizon they wrap, over the
+// poles they don't
+fn equirect_sampler() -> Sampler {
+    Sampler::new(Filter::Trilinear).with_address_mode(AddressMode::Repeat, AddressMode::ClampToEdge)
+}
+
+// linear rgb of an equirectangular image in the given direction, full resolution
+pub fn sample_equirect(texture: &Texture, direction: Vec3) -> Vec3 {
+    equirect_sampler()
+        .sample_level(texture, 0.0, equirect_uv(direction))
+        .xyz()
+}
+
+// Low frequency light from every direction, in the first 9 real spherical harmonics.
+// Good enough for diffuse lighting, which blurs away everything else anyway
+// (Ramamoorthi and Hanrahan, "An Efficient Representation for Irradiance Environment
+// Maps")
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SphericalHarmonics {
+    pub coefficients: [Vec3; 9],
+}
+
+impl SphericalHarmonics {
+    pub fn basis(d: Vec3) -> [f32; 9] {
+        [
+            0.282095,
+            0.488603 * d.y,
+            0.488603 * d.z,
+            0.488603 * d.x,
+            1.092548 * d.x * d.y,
+            1.092548 * d.y * d.z,
+            0.315392 * (3.0 * d.z * d.z - 1.0),
+            1.092548 * d.x * d.z,
+            0.546274 * (d.x * d.x - d.y * d.y),
+        ]
+    }
+
+    // Projects an equirectangular image, from the first mip level small enough
+    pub fn from_equirect(texture: &Texture) -> Self {
+        let level = (0..texture.level_count())
+            .find(|level| texture.level_size(*level).0 <= 64)
+            .unwrap_or(texture.level_count() - 1);
+        let (width, height) = texture.level_size(level);
+        let mut coefficients = [Vec3::ZERO; 9];
+        for y in 0..height {
+            for x in 0..width {
+                let uv = glam::vec2(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                // texels get smaller towards the poles
+                let solid_angle =
+                    (2.0 * PI / width as f32) * (PI / height as f32) * (uv.y * PI).sin();
+                let radiance = texture.texel(level, x, y).xyz() * solid_angle;
+                let basis = Self::basis(equirect_direction(uv));
+                for (coefficient, b) in coefficients.iter_mut().zip(basis) {
+                    *coefficient += radiance * b;
+                }
+            }
+        }
+        Self { coefficients }
+    }
+
+    // Irradiance: all the light arriving on a surface facing `normal`, cosine
+    // weighted. Divide by pi for the light a white Lambert surface reflects
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        // the cosine lobe convolved with each band
+        const BANDS: [f32; 9] = [
+            PI,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+        ];
+        let basis = Self::basis(normal.normalize_or_zero());
+        let mut irradiance = Vec3::ZERO;
+        for i in 0..9 {
+            irradiance += self.coefficients[i] * BANDS[i] * basis[i];
+        }
+        irradiance.max(Vec3::ZERO)
+    }
+}
+
+// The part of the split sum approximation (Karis, "Real Shading in Unreal Engine 4")
+// that only depends on n.v and roughness: the specular BRDF integrated over the
+// hemisphere comes down to f0 * scale + bias
+pub struct BrdfLut {
+    pub size: usize,
+    // (scale, bias), n.v along x and roughness along y
+    pub data: Vec<Vec2>,
+}
+
+impl BrdfLut {
+    pub fn new(size: usize, samples: u32) -> Self {
+        let mut data = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let n_dot_v = (x as f32 + 0.5) / size as f32;
+                let roughness = (y as f32 + 0.5) / size as f32;
+                data.push(integrate_brdf(n_dot_v, roughness, samples));
+            }
+        }
+        Self { size, data }
+    }
+
+    // bilinear, so the table can stay small
+    pub fn get(&self, n_dot_v: f32, roughness: f32) -> Vec2 {
+        let size = self.size as f32;
+        let p = glam::vec2(n_dot_v, roughness).clamp(Vec2::ZERO, Vec2::ONE) * size - 0.5;
+        let p = p.clamp(Vec2::ZERO, Vec2::splat(size - 1.0));
+        let (x, y) = (p.x as usize, p.y as usize);
+        let (x1, y1) = ((x + 1).min(self.size - 1), (y + 1).min(self.size - 1));
+        let t = p - p.floor();
+        let at = |x, y| self.data[coords_to_index(x, y, self.size)];
+        lerp(
+            lerp(at(x, y), at(x1, y), t.x),
+            lerp(at(x, y1), at(x1, y1), t.x),
+            t.y,
+        )
+    }
+}
+
+fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> Vec2 {
+    let alpha = alpha_from_roughness(roughness);
+    // any v with the right angle to n = +Z will do
+    let v = glam::vec3((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let mut result = Vec2::ZERO;
+    for i in 0..samples {
+        let h = importance_sample_ggx(hammersley(i, samples), alpha, Vec3::Z);
+        let v_dot_h = v.dot(h);
+        let l = 2.0 * v_dot_h * h - v;
+        let (n_dot_l, n_dot_h) = (l.z, h.z);
+        if n_dot_l <= 0.0 || n_dot_h <= 0.0 {
+            continue;
+        }
+        // brdf * n.l / pdf with the fresnel left out, pdf = D n.h / (4 v.h)
+        let weight =
+            visibility_smith_ggx(n_dot_l, n_dot_v, alpha) * 4.0 * n_dot_l * v_dot_h / n_dot_h;
+        let fresnel = (1.0 - v_dot_h).clamp(0.0, 1.0).powi(5);
+        result += glam::vec2(1.0 - fresnel, fresnel) * weight;
+    }
+    result / samples as f32
+}
+
+// well spread points in [0, 1)^2
+fn hammersley(i: u32, count: u32) -> Vec2 {
+    let radical_inverse = i.reverse_bits() as f32 * (1.0 / 4294967296.0);
+    glam::vec2(i as f32 / count as f32, radical_inverse)
+}
+
+// a microfacet normal around n, picked with the GGX distribution
+fn importance_sample_ggx(xi: Vec2, alpha: f32, n: Vec3) -> Vec3 {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let h = glam::vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+    let up = if n.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(tangent);
+    tangent * h.x + bitangent * h.y + n * h.z
+}
+
+pub struct EnvironmentMap {
+    // the image as loaded, for drawing it behind the scene
+    pub source: Texture,
+    pub irradiance: SphericalHarmonics,
+    // SPECULAR_LEVELS images, every one half the size of the previous one
+    pub specular: Vec<Texture>,
+    pub brdf_lut: BrdfLut,
+}
+
+impl EnvironmentMap {
+    // slow, do it once
+    pub fn new(source: Texture) -> Self {
+        let irradiance = SphericalHarmonics::from_equirect(&source);
+        let width = source.width.min(256);
+        let specular = (0..SPECULAR_LEVELS)
+            .map(|level| {
+                let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+                let width = (width >> level).max(8);
+                prefilter(&source, roughness, width, width / 2)
+            })
+            .collect();
+        Self {
+            source,
+            irradiance,
+            specular,
+            brdf_lut: BrdfLut::new(32, 128),
+        }
+    }
+
+    // light arriving from `direction`, as seen in the image
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        sample_equirect(&self.source, direction)
+    }
+
+    // light reflected in `direction` by a surface of the given roughness, blended
+    // between the two closest prefiltered images
+    pub fn specular(&self, direction: Vec3, roughness: f32) -> Vec3 {
+        let uv = equirect_uv(direction);
+        let sampler = equirect_sampler();
+        let level = roughness.clamp(0.0, 1.0) * (SPECULAR_LEVELS - 1) as f32;
+        let fine = level.floor() as usize;
+        let coarse = (fine + 1).min(SPECULAR_LEVELS - 1);
+        lerp(
+            sampler.sample_level(&self.specular[fine], 0.0, uv),
+            sampler.sample_level(&self.specular[coarse], 0.0, uv),
+            level.fract(),
+        )
+        .xyz()
+    }
+
+    // Light from the whole environment reflected towards v, see SurfaceParams::shade
+    pub fn shade(&self, surface: &SurfaceParams, n: Vec3, v: Vec3) -> Vec3 {
+        let n_dot_v = n.dot(v).max(1e-4);
+        let diffuse = surface.diffuse_color() * self.irradiance.irradiance(n) / PI;
+        let reflected = 2.0 * n_dot_v * n - v;
+        let lut = self.brdf_lut.get(n_dot_v, surface.roughness);
+        let specular = self.specular(reflected, surface.roughness) * (surface.f0() * lut.x + lut.y);
+        diffuse + specular
+    }
+}
+
+// The image as a surface of the given roughness would reflect it, assuming it is
+// seen straight on (n = v = r)
+fn prefilter(source: &Texture, roughness: f32, width: usize, height: usize) -> Texture {
+    let sampler = equirect_sampler();
+    let alpha = alpha_from_roughness(roughness);
+    // every source texel, roughly, as a solid angle
+    let texel_solid_angle = 4.0 * PI / (source.width * source.height) as f32;
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let uv = glam::vec2(
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            );
+            if roughness == 0.0 {
+                // a mirror, only the size changes
+                let lod = (source.width as f32 / width as f32).log2().max(0.0);
+                data.push(sampler.sample_level(source, lod, uv));
+                continue;
+            }
+
+            let n = equirect_direction(uv);
+            let mut sum = Vec3::ZERO;
+            let mut weight = 0.0;
+            for i in 0..PREFILTER_SAMPLES {
+                let h = importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), alpha, n);
+                let n_dot_h = n.dot(h).max(0.0);
+                let l = 2.0 * n_dot_h * h - n;
+                let n_dot_l = n.dot(l);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+                // Few samples on a sharp image give fireflies, every sample reads a
+                // mip level as big as the solid angle it stands for instead (GPU Gems
+                // 3, chapter 20). With n = v the pdf is D / 4
+                let pdf = distribution_ggx(n_dot_h, alpha) / 4.0;
+                let sample_solid_angle = 1.0 / (PREFILTER_SAMPLES as f32 * pdf + 1e-4);
+                let lod = (0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0)
+                    .clamp(0.0, (source.level_count() - 1) as f32);
+                sum += sampler.sample_level(source, lod, equirect_uv(l)).xyz() * n_dot_l;
+                weight += n_dot_l;
+            }
+            data.push((sum / weight.max(1e-4)).extend(1.0));
+        }
+    }
+    Texture::new_float(width, height, data, 4)
+}
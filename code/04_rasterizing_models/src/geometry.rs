@@ -1,5 +1,7 @@
+use crate::varyings::ClipVertex;
 use glam::{Mat4, UVec3, Vec2, Vec3, Vec4, Vec4Swizzles};
-use std::ops::{Add, AddAssign, Mul, MulAssign, Sub};
+use std::fmt;
+use std::ops::{Add, AddAssign};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Vertex {
@@ -7,7 +9,10 @@ pub struct Vertex {
     pub normal: Vec3,
     pub color: Vec3,
     pub uv: Vec2,
-    //pub normal: Vec3,
+    // xyz points where u grows along the surface, w is +1 or -1 and flips the
+    // bitangent: cross(normal, tangent) * w. Zero when the mesh has none, see
+    // Mesh::generate_tangents
+    pub tangent: Vec4,
 }
 
 impl Vertex {
@@ -17,77 +22,64 @@ impl Vertex {
             normal,
             color,
             uv,
+            tangent: Vec4::ZERO,
         }
     }
 }
 
-impl Add for Vertex {
-    type Output = Self;
-
-    fn add(self, rhs: Self) -> Self {
-        let position = self.position + rhs.position;
-        let normal = self.normal + rhs.normal;
-        let color = self.color + rhs.color;
-        let uv = self.uv + rhs.uv;
-        Self {
-            position,
-            normal,
-            color,
-            uv,
-        }
-    }
-}
-
-impl Sub for Vertex {
-    type Output = Self;
-
-    fn sub(self, rhs: Self) -> Self {
-        let position = self.position - rhs.position;
-        let normal = self.normal - rhs.normal;
-        let color = self.color - rhs.color;
-        let uv = self.uv - rhs.uv;
-        Self {
-            position,
-            normal,
-            color,
-            uv,
-        }
-    }
+crate::impl_varyings!(Vertex {
+    position,
+    normal,
+    color,
+    uv,
+    tangent
+});
+
+// A glTF primitive whose accessors don't fit together
+#[derive(Debug)]
+pub enum MeshError {
+    // an attribute doesn't have one element per vertex
+    AttributeLength {
+        attribute: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    // an index points past the last vertex
+    IndexOutOfRange {
+        index: u32,
+        vertices: usize,
+    },
 }
 
-impl Mul<f32> for Vertex {
-    type Output = Self;
-
-    fn mul(self, rhs: f32) -> Self {
-        let position = self.position * rhs;
-        let normal = self.normal * rhs;
-        let color = self.color * rhs;
-        let uv = self.uv * rhs;
-        Self {
-            position,
-            normal,
-            color,
-            uv,
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::AttributeLength {
+                attribute,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} has {} elements for {} vertices",
+                attribute, found, expected
+            ),
+            MeshError::IndexOutOfRange { index, vertices } => {
+                write!(f, "index {} out of {} vertices", index, vertices)
+            }
         }
     }
 }
 
-impl MulAssign<f32> for Vertex {
-    fn mul_assign(&mut self, rhs: f32) {
-        self.position *= rhs;
-        self.normal *= rhs;
-        self.color *= rhs;
-        self.uv *= rhs;
-    }
-}
+impl std::error::Error for MeshError {}
 
+// generic over the vertex type, so meshes can carry whatever the vertex shader consumes
 #[derive(Debug, Clone)]
-pub struct Mesh {
+pub struct Mesh<V = Vertex> {
     triangles: Vec<UVec3>,
-    vertices: Vec<Vertex>,
+    vertices: Vec<V>,
 }
 
-impl Mesh {
+impl<V: Copy> Mesh<V> {
     pub fn new() -> Self {
         Self {
             triangles: Vec::new(),
@@ -99,11 +91,11 @@ impl Mesh {
         &self.triangles
     }
 
-    pub fn vertices(&self) -> &Vec<Vertex> {
+    pub fn vertices(&self) -> &Vec<V> {
         &self.vertices
     }
 
-    pub fn get_vertices_from_triangle(&self, triangle: UVec3) -> [&Vertex; 3] {
+    pub fn get_vertices_from_triangle(&self, triangle: UVec3) -> [&V; 3] {
         [
             &self.vertices[triangle.x as usize],
             &self.vertices[triangle.y as usize],
@@ -111,20 +103,22 @@ impl Mesh {
         ]
     }
 
-    pub fn from_vertices(triangles: &[UVec3], vertices: &[Vertex]) -> Self {
-        let mut mesh = Mesh::new();
+    pub fn from_vertices(triangles: &[UVec3], vertices: &[V]) -> Self {
+        let mut mesh = Self::new();
         mesh.add_section_from_vertices(triangles, vertices);
         mesh
     }
 
     // we can also do it with slices
-    pub fn add_section_from_vertices(&mut self, triangles: &[UVec3], vertices: &[Vertex]) {
+    pub fn add_section_from_vertices(&mut self, triangles: &[UVec3], vertices: &[V]) {
         let offset = self.vertices.len() as u32;
         let triangles: Vec<UVec3> = triangles.iter().map(|tri| *tri + offset).collect();
         self.triangles.extend_from_slice(&triangles);
         self.vertices.extend_from_slice(vertices);
     }
+}
 
+impl Mesh {
     pub fn add_section_from_buffers(
         &mut self,
         triangles: &[UVec3],
@@ -132,70 +126,247 @@ impl Mesh {
         normals: &[Vec3],
         colors: &[Vec3],
         uvs: &[Vec2],
+        tangents: &[Vec4],
     ) {
         self.triangles.extend_from_slice(triangles);
 
         let has_uvs = !uvs.is_empty();
         let has_colors = !colors.is_empty();
+        let has_tangents = !tangents.is_empty();
 
         for i in 0..positions.len() {
-            let vertex = Vertex::new(
-                positions[i].extend(1.0),
-                normals[i],
-                if has_colors { colors[i] } else { Vec3::ONE },
-                if has_uvs { uvs[i] } else { Vec2::ZERO },
-            );
+            let vertex = Vertex {
+                tangent: if has_tangents {
+                    tangents[i]
+                } else {
+                    Vec4::ZERO
+                },
+                ..Vertex::new(
+                    positions[i].extend(1.0),
+                    normals[i],
+                    if has_colors { colors[i] } else { Vec3::ONE },
+                    if has_uvs { uvs[i] } else { Vec2::ZERO },
+                )
+            };
             self.vertices.push(vertex)
         }
     }
 
-    pub fn load_from_gltf(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Mesh {
+    // MikkTSpace tangents, what glTF expects when a normal map comes without them.
+    // The mikktspace crate works per triangle corner: where the corners sharing a
+    // vertex disagree (uv seams, mirrored uvs) the vertex gets split, one copy per
+    // tangent. Meshes without usable uvs keep zero tangents
+    pub fn generate_tangents(&mut self) {
+        let has_uvs = self.triangles.iter().any(|triangle| {
+            let uv = [triangle.x, triangle.y, triangle.z].map(|id| self.vertices[id as usize].uv);
+            (uv[1] - uv[0]).perp_dot(uv[2] - uv[0]).abs() > 1e-12
+        });
+        if !has_uvs {
+            return;
+        }
+        let mut geometry = TangentSpace {
+            mesh: self,
+            corners: vec![Vec4::ZERO; self.triangles.len() * 3],
+        };
+        if !bevy_mikktspace::generate_tangents(&mut geometry) {
+            return;
+        }
+        let corners = geometry.corners;
+
+        // copies[id]: the vertices split off vertex id, each with another tangent
+        let mut copies = vec![Vec::new(); self.vertices.len()];
+        let mut assigned = vec![false; self.vertices.len()];
+        for (face, triangle) in self.triangles.iter_mut().enumerate() {
+            for corner in 0..3 {
+                let tangent = corners[face * 3 + corner];
+                let id = triangle[corner] as usize;
+                if !assigned[id] {
+                    assigned[id] = true;
+                    self.vertices[id].tangent = tangent;
+                    continue;
+                }
+                let matches = |vertex: &Vertex| vertex.tangent.abs_diff_eq(tangent, 1e-5);
+                if matches(&self.vertices[id]) {
+                    continue;
+                }
+                let copy = copies[id]
+                    .iter()
+                    .copied()
+                    .find(|copy| matches(&self.vertices[*copy as usize]));
+                triangle[corner] = copy.unwrap_or_else(|| {
+                    let copy = self.vertices.len() as u32;
+                    self.vertices.push(Vertex {
+                        tangent,
+                        ..self.vertices[id]
+                    });
+                    copies[id].push(copy);
+                    copy
+                });
+            }
+        }
+    }
+
+    pub fn load_from_gltf(
+        mesh: &gltf::Mesh,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Mesh, MeshError> {
+        let mut result = Mesh::new();
+        for primitive in mesh.primitives() {
+            result += Mesh::load_from_gltf_primitive(&primitive, buffers)?;
+        }
+        Ok(result)
+    }
+
+    pub fn load_from_gltf_primitive(
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Mesh, MeshError> {
         let mut positions: Vec<Vec3> = Vec::new();
         let mut tex_coords: Vec<Vec2> = Vec::new();
         let mut normals: Vec<Vec3> = Vec::new();
-        let mut indices = vec![];
-        // TODO: handle errors
+        let mut tangents: Vec<Vec4> = Vec::new();
         let mut result = Mesh::new();
-        for primitive in mesh.primitives() {
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-            if let Some(indices_reader) = reader.read_indices() {
-                indices_reader.into_u32().for_each(|i| indices.push(i));
-            }
-            if let Some(positions_reader) = reader.read_positions() {
-                positions_reader.for_each(|p| positions.push(Vec3::new(p[0], p[1], p[2])));
-            }
-            if let Some(normals_reader) = reader.read_normals() {
-                normals_reader.for_each(|n| normals.push(Vec3::new(n[0], n[1], n[2])));
-            }
-            if let Some(tex_coord_reader) = reader.read_tex_coords(0) {
-                tex_coord_reader
-                    .into_f32()
-                    .for_each(|tc| tex_coords.push(Vec2::new(tc[0], tc[1])));
-            }
-
-            let colors: Vec<Vec3> = positions.iter().map(|_| Vec3::ONE).collect();
-            println!("Num indices: {:?}", indices.len());
-            println!("tex_coords: {:?}", tex_coords.len());
-            println!("positions: {:?}", positions.len());
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let indices: Option<Vec<u32>> = reader
+            .read_indices()
+            .map(|indices_reader| indices_reader.into_u32().collect());
+        if let Some(positions_reader) = reader.read_positions() {
+            positions_reader.for_each(|p| positions.push(Vec3::new(p[0], p[1], p[2])));
+        }
+        if let Some(normals_reader) = reader.read_normals() {
+            normals_reader.for_each(|n| normals.push(Vec3::new(n[0], n[1], n[2])));
+        }
+        if let Some(tangents_reader) = reader.read_tangents() {
+            tangents_reader.for_each(|t| tangents.push(Vec4::from(t)));
+        }
+        if let Some(tex_coord_reader) = reader.read_tex_coords(0) {
+            tex_coord_reader
+                .into_f32()
+                .for_each(|tc| tex_coords.push(Vec2::new(tc[0], tc[1])));
+        }
 
-            let triangles: Vec<UVec3> = indices
+        // every attribute there is has one element per vertex
+        let vertex_count = positions.len();
+        for (attribute, found) in [
+            ("NORMAL", normals.len()),
+            ("TANGENT", tangents.len()),
+            ("TEXCOORD_0", tex_coords.len()),
+        ] {
+            if found != 0 && found != vertex_count {
+                return Err(MeshError::AttributeLength {
+                    attribute,
+                    expected: vertex_count,
+                    found,
+                });
+            }
+        }
+        // without indices the vertices are drawn in order
+        let mut indices = indices.unwrap_or_else(|| (0..vertex_count as u32).collect());
+        if let Some(&index) = indices.iter().find(|i| **i as usize >= vertex_count) {
+            return Err(MeshError::IndexOutOfRange {
+                index,
+                vertices: vertex_count,
+            });
+        }
+        indices.truncate(indices.len() / 3 * 3);
+
+        // The spec asks for flat normals when there are none. Every triangle gets its
+        // own three vertices so they can point the way it faces
+        if normals.is_empty() {
+            positions = unshare(&positions, &indices);
+            tex_coords = unshare(&tex_coords, &indices);
+            tangents = unshare(&tangents, &indices);
+            normals = positions
                 .chunks_exact(3)
-                .map(|tri| UVec3::new(tri[0], tri[1], tri[2]))
+                .flat_map(|p| [(p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero(); 3])
                 .collect();
-            result.add_section_from_buffers(&triangles, &positions, &normals, &colors, &tex_coords)
+            indices = (0..positions.len() as u32).collect();
         }
-        result
+
+        let colors: Vec<Vec3> = positions.iter().map(|_| Vec3::ONE).collect();
+        println!("Num indices: {:?}", indices.len());
+        println!("tex_coords: {:?}", tex_coords.len());
+        println!("positions: {:?}", positions.len());
+
+        let triangles: Vec<UVec3> = indices
+            .chunks_exact(3)
+            .map(|tri| UVec3::new(tri[0], tri[1], tri[2]))
+            .collect();
+        result.add_section_from_buffers(
+            &triangles,
+            &positions,
+            &normals,
+            &colors,
+            &tex_coords,
+            &tangents,
+        );
+        // the spec asks for MikkTSpace when the file doesn't have them
+        if tangents.is_empty() && !tex_coords.is_empty() {
+            result.generate_tangents();
+        }
+        Ok(result)
+    }
+}
+
+// one value per index, attributes the file doesn't have stay empty
+fn unshare<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    indices.iter().map(|i| values[*i as usize]).collect()
+}
+
+// What the mikktspace crate reads the mesh through, tangents come out per triangle
+// corner
+struct TangentSpace<'a> {
+    mesh: &'a Mesh,
+    corners: Vec<Vec4>,
+}
+
+impl TangentSpace<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.mesh.vertices[self.mesh.triangles[face][vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentSpace<'_> {
+    fn num_faces(&self) -> usize {
+        self.mesh.triangles.len()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position.xyz().to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert)
+            .normal
+            .normalize_or_zero()
+            .to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).uv.to_array()
+    }
+
+    // w is the handedness, same convention as glTF
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corners[face * 3 + vert] = Vec4::from(tangent);
     }
 }
 
 // for more on struct initialization check Default trait
-impl Default for Mesh {
+impl<V: Copy> Default for Mesh<V> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Add for Mesh {
+impl<V: Copy> Add for Mesh<V> {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
@@ -205,12 +376,13 @@ impl Add for Mesh {
     }
 }
 
-impl AddAssign for Mesh {
+impl<V: Copy> AddAssign for Mesh<V> {
     fn add_assign(&mut self, rhs: Self) {
         self.add_section_from_vertices(rhs.triangles(), rhs.vertices());
     }
 }
 
+#[derive(Debug, Copy, Clone)]
 pub struct BoundingBox2D {
     pub left: f32,
     pub right: f32,
@@ -233,12 +405,15 @@ pub fn get_triangle_bounding_box_2d(positions: &[Vec2; 3]) -> BoundingBox2D {
 }
 
 #[derive(Debug, Copy, Clone)]
-pub struct Triangle {
-    pub v0: Vertex,
-    pub v1: Vertex,
-    pub v2: Vertex,
+pub struct Triangle<V = Vertex> {
+    pub v0: V,
+    pub v1: V,
+    pub v2: V,
 }
 
+// what the clipper and the rasterizer work on, after the vertex shader ran
+pub type ClipTriangle<V> = Triangle<ClipVertex<V>>;
+
 pub enum VerticesOrder {
     ABC,
     ACB,
@@ -248,11 +423,24 @@ pub enum VerticesOrder {
     CBA,
 }
 
-impl Triangle {
-    pub fn new(v0: Vertex, v1: Vertex, v2: Vertex) -> Self {
+impl<V: Copy> Triangle<V> {
+    pub fn new(v0: V, v1: V, v2: V) -> Self {
         Self { v0, v1, v2 }
     }
 
+    pub fn reorder(&self, order: VerticesOrder) -> Self {
+        match order {
+            VerticesOrder::ABC => *self,
+            VerticesOrder::ACB => Self::new(self.v0, self.v2, self.v1),
+            VerticesOrder::BAC => Self::new(self.v1, self.v0, self.v2),
+            VerticesOrder::BCA => Self::new(self.v1, self.v2, self.v0),
+            VerticesOrder::CAB => Self::new(self.v2, self.v0, self.v1),
+            VerticesOrder::CBA => Self::new(self.v2, self.v1, self.v0),
+        }
+    }
+}
+
+impl Triangle {
     pub fn transform(&self, matrix: &Mat4) -> Self {
         let p0 = *matrix * self.v0.position.xyz().extend(1.0);
         let p1 = *matrix * self.v1.position.xyz().extend(1.0);
@@ -266,15 +454,4 @@ impl Triangle {
 
         result
     }
-
-    pub fn reorder(&self, order: VerticesOrder) -> Self {
-        match order {
-            VerticesOrder::ABC => *self,
-            VerticesOrder::ACB => Self::new(self.v0, self.v2, self.v1),
-            VerticesOrder::BAC => Self::new(self.v1, self.v0, self.v2),
-            VerticesOrder::BCA => Self::new(self.v1, self.v2, self.v0),
-            VerticesOrder::CAB => Self::new(self.v2, self.v0, self.v1),
-            VerticesOrder::CBA => Self::new(self.v2, self.v1, self.v0),
-        }
-    }
 }
@@ -1,16 +1,47 @@
-use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
 use std::path::Path;
+pub mod background;
+pub mod brdf;
 pub mod camera;
+pub mod environment;
 pub mod geometry;
+pub mod light;
+pub mod material;
+pub mod model;
+pub mod post_process;
+pub mod render_settings;
+pub mod render_target;
+pub mod sampler;
+pub mod shader;
+pub mod shadow;
 pub mod texture;
+pub mod tiled;
 pub mod transform;
 pub mod utils;
+pub mod varyings;
 pub use {
+    background::{Background, CubeMap},
+    brdf::SurfaceParams,
     camera::Camera,
+    environment::{BrdfLut, EnvironmentMap, SphericalHarmonics},
     geometry::*,
-    texture::Texture,
+    light::Light,
+    material::{AlphaMode, GltfTextures, Material, MaterialTexture},
+    model::Model,
+    post_process::{PostProcess, ToneMapper},
+    render_settings::{BlendMode, DepthCompare, DepthState, RenderSettings},
+    render_target::{RenderTarget, TargetView, Tile},
+    sampler::{AddressMode, Filter, MipmapMode, Sampler, TexelFilter, MAX_ANISOTROPY},
+    shader::{
+        perturb_normal, DefaultShader, DefaultVaryings, Fragment, FragmentShader, PbrShader,
+        PbrVaryings, VertexShader,
+    },
+    shadow::{ShadowMap, ShadowSettings},
+    texture::{ColorSpace, MipLevel, Texels, Texture, TextureError},
+    tiled::{raster_mesh_tiled, raster_mesh_tiled_with_workers, TILE_SIZE},
     transform::{Transform, TransformInitialParams},
     utils::*,
+    varyings::{ClipVertex, Varyings},
 };
 
 #[cfg(test)]
@@ -25,12 +56,14 @@ mod tests {
             normal: glam::vec3(0.0, 0.0, 1.0),
             color: glam::vec3(0.0, 1.0, 1.0),
             uv: glam::vec2(0.0, 0.0),
+            tangent: glam::Vec4::ZERO,
         };
         let v1 = Vertex {
             position: glam::vec4(100.0, 400.0, 0.0, 1.0),
             normal: glam::vec3(0.0, 0.0, 1.0),
             color: glam::vec3(1.0, 0.0, 0.0),
             uv: glam::vec2(0.0, 1.0),
+            tangent: glam::Vec4::ZERO,
         };
 
         let interpolated = lerp(v0, v1, 0.5);
@@ -38,134 +71,384 @@ mod tests {
     }
 }
 
-pub fn raster_clipped_triangle(
-    clip_triangle: &Triangle,
-    texture: Option<&Texture>,
-    buffer: &mut Vec<u32>,
-    z_buffer: &mut Vec<f32>,
-    viewport_size: Vec2,
+// Everything about a clipped triangle that doesn't change from pixel to pixel,
+// computed once and then reused for every pixel (and every tile) it covers
+#[derive(Debug, Copy, Clone)]
+pub struct TriangleSetup<V> {
+    // screeen coordinates remapped to window
+    pub screen: [Vec2; 3],
+    // 1 / w, for perspective correct interpolation
+    pub rec_w: [f32; 3],
+    pub depth: [f32; 3],
+    // varyings already divided by w
+    pub varyings: [V; 3],
+    // oriented so the inside of the triangle is positive for all three,
+    // edge i is the one opposite to vertex i
+    pub edges: [EdgeEquation; 3],
+    pub inv_area: f32,
+    pub bounding_box: BoundingBox2D,
+    // color multiplied into the output, set by the clip debug visualization
+    pub tint: Option<Vec3>,
+}
+
+impl<V: Varyings> TriangleSetup<V> {
+    // None when the triangle doesn't touch the viewport
+    pub fn new(clip_triangle: &ClipTriangle<V>, viewport_size: Vec2) -> Option<Self> {
+        let rec0 = 1.0 / clip_triangle.v0.position.w;
+        let rec1 = 1.0 / clip_triangle.v1.position.w;
+        let rec2 = 1.0 / clip_triangle.v2.position.w;
+
+        // This would be the output of the vertex shader (clip space)
+        // then we perform perspective division to transform in ndc
+        // now x,y,z componend of ndc are between -1 and 1
+        let ndc0 = clip_triangle.v0.position * rec0;
+        let ndc1 = clip_triangle.v1.position * rec1;
+        let ndc2 = clip_triangle.v2.position * rec2;
+
+        // perspective division on all attributes
+        let v0 = clip_triangle.v0.varyings * rec0;
+        let v1 = clip_triangle.v1.varyings * rec1;
+        let v2 = clip_triangle.v2.varyings * rec2;
+
+        let sc0 = glam::vec2(
+            map_to_range(ndc0.x, -1.0, 1.0, 0.0, viewport_size.x),
+            map_to_range(-ndc0.y, -1.0, 1.0, 0.0, viewport_size.y),
+        );
+        let sc1 = glam::vec2(
+            map_to_range(ndc1.x, -1.0, 1.0, 0.0, viewport_size.x),
+            map_to_range(-ndc1.y, -1.0, 1.0, 0.0, viewport_size.y),
+        );
+        let sc2 = glam::vec2(
+            map_to_range(ndc2.x, -1.0, 1.0, 0.0, viewport_size.x),
+            map_to_range(-ndc2.y, -1.0, 1.0, 0.0, viewport_size.y),
+        );
+
+        // snapping makes shared edges land on exactly the same positions
+        let [fx0, fx1, fx2] = [sc0, sc1, sc2].map(snap_to_subpixel);
+        let [sc0, sc1, sc2] = [fx0, fx1, fx2].map(from_subpixel);
+
+        let mut edges = [
+            EdgeEquation::new(fx1, fx2),
+            EdgeEquation::new(fx2, fx0),
+            EdgeEquation::new(fx0, fx1),
+        ];
+        let mut area = edges[0].evaluate(fx0);
+        if area == 0 {
+            return None;
+        }
+        // winding on screen depends on the culling mode, accept both
+        if area < 0 {
+            edges.iter_mut().for_each(EdgeEquation::flip);
+            area = -area;
+        }
+
+        let bounding_box = triangle_screen_bounding_box(&[sc0, sc1, sc2], viewport_size)?;
+        Some(Self {
+            screen: [sc0, sc1, sc2],
+            rec_w: [rec0, rec1, rec2],
+            depth: [ndc0.z, ndc1.z, ndc2.z],
+            varyings: [v0, v1, v2],
+            edges,
+            inv_area: 1.0 / area as f32,
+            bounding_box,
+            tint: None,
+        })
+    }
+}
+
+// pixels are tested against the triangle edges in square blocks first
+pub const RASTER_BLOCK_SIZE: usize = 8;
+
+// Rasterizes the part of the triangle that falls inside the view.
+// The bounding box is walked in blocks: a block with all four corners outside one edge
+// is skipped as a whole, a block with all corners inside every edge needs no per pixel
+// test. Inside a block the edge functions are stepped incrementally along each row.
+// The view has to start on even coordinates, pixels get shaded in 2x2 quads.
+pub fn raster_triangle_setup<V: Varyings, F: FragmentShader<V>>(
+    setup: &TriangleSetup<V>,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    view: &mut TargetView,
 ) {
-    let rec0 = 1.0 / clip_triangle.v0.position.w;
-    let rec1 = 1.0 / clip_triangle.v1.position.w;
-    let rec2 = 1.0 / clip_triangle.v2.position.w;
-
-    // This would be the output of the vertex shader (clip space)
-    // then we perform perspective division to transform in ndc
-    // now x,y,z componend of ndc are between -1 and 1
-    let ndc0 = clip_triangle.v0.position * rec0;
-    let ndc1 = clip_triangle.v1.position * rec1;
-    let ndc2 = clip_triangle.v2.position * rec2;
-
-    // perspective division on all attributes
-    let v0 = clip_triangle.v0 * rec0;
-    let v1 = clip_triangle.v1 * rec1;
-    let v2 = clip_triangle.v2 * rec2;
+    let bb = &setup.bounding_box;
+    let left = (bb.left as usize).max(view.left);
+    let right = (bb.right as usize).min(view.left + view.width - 1);
+    let top = (bb.top as usize).max(view.top);
+    let bottom = (bb.bottom as usize).min(view.top + view.height - 1);
+
+    let [rec0, rec1, rec2] = setup.rec_w;
+    let depth_state = &settings.depth;
+    let [z0, z1, z2] = setup.depth.map(|z| depth_state.map_to_range(z));
+    let [v0, v1, v2] = setup.varyings;
+    let edges = &setup.edges;
+    let step_x = edges.map(|e| e.step_x());
+    let min_inside = edges.map(|e| e.min_inside());
+    let evaluate = |x: usize, y: usize| {
+        let p = subpixel_center(x, y);
+        edges.map(|e| e.evaluate(p))
+    };
+    let inside = |weights: &[i64; 3]| (0..3).all(|i| weights[i] >= min_inside[i]);
+
+    // Pixels are shaded in 2x2 quads sitting on even screen coordinates, so fragments
+    // know how fast their varyings change from one pixel to the next (e.g. to pick a mip).
+    // Pixels of a quad outside the triangle are interpolated too, but never written
+    let mut shade_quad = |x: usize, y: usize, weights: [[i64; 3]; 4], covered: [bool; 4]| {
+        let pixels = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)];
+        let pixel_ids =
+            pixels.map(|(px, py)| coords_to_index(px - view.left, py - view.top, view.width));
+        let bary =
+            weights.map(|w| glam::vec3(w[0] as f32, w[1] as f32, w[2] as f32) * setup.inv_area);
+        let depth = bary.map(|b| b.x * z0 + b.y * z1 + b.z * z2);
+        let passed = [0, 1, 2, 3]
+            .map(|i| covered[i] && depth_state.compare.test(depth[i], view.depth[pixel_ids[i]]));
+        if !passed.contains(&true) {
+            return;
+        }
+        let skip_shading = settings.depth_only && settings.alpha_cutoff.is_none();
+        if skip_shading {
+            if depth_state.write {
+                for i in (0..4).filter(|i| passed[*i]) {
+                    view.depth[pixel_ids[i]] = depth[i];
+                }
+            }
+            return;
+        }
 
-    // screeen coordinates remapped to window
-    let sc0 = glam::vec2(
-        map_to_range(ndc0.x, -1.0, 1.0, 0.0, viewport_size.x),
-        map_to_range(-ndc0.y, -1.0, 1.0, 0.0, viewport_size.y),
-    );
-    let sc1 = glam::vec2(
-        map_to_range(ndc1.x, -1.0, 1.0, 0.0, viewport_size.x),
-        map_to_range(-ndc1.y, -1.0, 1.0, 0.0, viewport_size.y),
-    );
-    let sc2 = glam::vec2(
-        map_to_range(ndc2.x, -1.0, 1.0, 0.0, viewport_size.x),
-        map_to_range(-ndc2.y, -1.0, 1.0, 0.0, viewport_size.y),
-    );
-
-    if let Some(bb) = triangle_screen_bounding_box(&[sc0, sc1, sc2], viewport_size) {
-        for y in (bb.top as usize)..=bb.bottom as usize {
-            for x in (bb.left as usize)..=bb.right as usize {
-                let coords = glam::vec2(x as f32, y as f32) + 0.5;
-                let pixel_id = coords_to_index(x, y, viewport_size.x as usize);
-                let area = edge_function(sc0, sc1, sc2);
-
-                if let Some(bary) = barycentric_coordinates(coords, sc0, sc1, sc2, area) {
-                    let correction = bary.x * rec0 + bary.y * rec1 + bary.z * rec2;
-                    let correction = 1.0 / correction;
-                    let depth = bary.x * ndc0.z + bary.y * ndc1.z + bary.z * ndc2.z;
-                    if depth < z_buffer[pixel_id] {
-                        z_buffer[pixel_id] = depth;
-                        let normal = bary.x * v0.normal + bary.y * v1.normal + bary.z * v2.normal;
-                        let normal = normal * correction;
-                        let n_dot_l = normal.dot(Vec3::ONE.normalize());
-                        let color = bary.x * v0.color + bary.y * v1.color + bary.z * v2.color;
-                        let mut color = color * correction;
-                        if let Some(tex) = texture {
-                            let tex_coords = bary.x * v0.uv + bary.y * v1.uv + bary.z * v2.uv;
-                            let tex_coords = tex_coords * correction;
-                            color = tex.argb_at_uvf(tex_coords.x, tex_coords.y).yzw();
-                        }
-                        let ambient = glam::vec3(0.2, 0.2, 0.2);
-                        color = color * n_dot_l + ambient;
-                        let out_color = to_argb8(
-                            255,
-                            (color.x * 255.0) as u8,
-                            (color.y * 255.0) as u8,
-                            (color.z * 255.0) as u8,
-                        );
-                        buffer[pixel_id] = out_color;
+        let varyings = bary.map(|b| {
+            let correction = 1.0 / (b.x * rec0 + b.y * rec1 + b.z * rec2);
+            (v0 * b.x + v1 * b.y + v2 * b.z) * correction
+        });
+        for i in (0..4).filter(|i| passed[*i]) {
+            let (px, py) = pixels[i];
+            // first pixel of the same row and of the same column in the quad
+            let (row, column) = (i & 2, i & 1);
+            let fragment = Fragment {
+                coords: glam::vec2(px as f32, py as f32) + 0.5,
+                depth: depth[i],
+                varyings: varyings[i],
+                ddx: varyings[row + 1] - varyings[row],
+                ddy: varyings[column + 2] - varyings[column],
+            };
+            if let Some(mut color) = fragment_shader.shade_fragment(&fragment) {
+                if let Some(cutoff) = settings.alpha_cutoff {
+                    if color.w < cutoff {
+                        continue;
+                    }
+                    color.w = 1.0;
+                }
+                let pixel_id = pixel_ids[i];
+                if settings.depth_only {
+                    if depth_state.write {
+                        view.depth[pixel_id] = depth[i];
                     }
+                    continue;
                 }
+                if let Some(tint) = setup.tint {
+                    color = (color.xyz() * tint).extend(color.w);
+                }
+                if settings.blend != BlendMode::Opaque {
+                    color = settings.blend.blend(color, view.color[pixel_id]);
+                }
+                if depth_state.write {
+                    view.depth[pixel_id] = depth[i];
+                }
+                view.color[pixel_id] = color;
             }
         }
+    };
+    // walks the quads starting at (left, top), both have to be even
+    let mut raster_rect =
+        |rect_left: usize, rect_right: usize, rect_top: usize, rect_bottom: usize, test: bool| {
+            let step = |w: [i64; 3]| [w[0] + step_x[0], w[1] + step_x[1], w[2] + step_x[2]];
+            for y in (rect_top..=rect_bottom).step_by(2) {
+                let mut upper = evaluate(rect_left, y);
+                let mut lower = evaluate(rect_left, y + 1);
+                for x in (rect_left..=rect_right).step_by(2) {
+                    let weights = [upper, step(upper), lower, step(lower)];
+                    let covered = [0, 1, 2, 3].map(|i| {
+                        let (px, py) = (x + (i & 1), y + i / 2);
+                        (left..=right).contains(&px)
+                            && (top..=bottom).contains(&py)
+                            && (!test || inside(&weights[i]))
+                    });
+                    if covered.contains(&true) {
+                        shade_quad(x, y, weights, covered);
+                    }
+                    upper = step(weights[1]);
+                    lower = step(weights[3]);
+                }
+            }
+        };
+    // views always start on even coordinates, so this never leaves the view
+    let align = |v: usize| v & !1;
+
+    // small triangles would spend more time on block corners than on actual pixels
+    let block = RASTER_BLOCK_SIZE as f32;
+    if bb.right - bb.left < block && bb.bottom - bb.top < block {
+        raster_rect(align(left), right, align(top), bottom, true);
+        return;
+    }
+
+    for block_top in (align(top)..=bottom).step_by(RASTER_BLOCK_SIZE) {
+        let block_bottom = (block_top + RASTER_BLOCK_SIZE - 1).min(bottom);
+        for block_left in (align(left)..=right).step_by(RASTER_BLOCK_SIZE) {
+            let block_right = (block_left + RASTER_BLOCK_SIZE - 1).min(right);
+
+            let corners = [
+                evaluate(block_left, block_top),
+                evaluate(block_right, block_top),
+                evaluate(block_left, block_bottom),
+                evaluate(block_right, block_bottom),
+            ];
+            // edge functions are linear, so the corners bound the whole block
+            let outside = (0..3).any(|i| corners.iter().all(|c| c[i] < min_inside[i]));
+            if outside {
+                continue;
+            }
+            let fully_covered = corners.iter().all(inside);
+            raster_rect(
+                block_left,
+                block_right,
+                block_top,
+                block_bottom,
+                !fully_covered,
+            );
+        }
     }
 }
 
-pub fn raster_triangle(
-    vertices: &[&Vertex; 3],
-    model: &Mat4,
-    mvp: &Mat4,
-    texture: Option<&Texture>,
-    buffer: &mut Vec<u32>,
-    z_buffer: &mut Vec<f32>,
-    viewport_size: Vec2,
+pub fn raster_clipped_triangle<V: Varyings, F: FragmentShader<V>>(
+    clip_triangle: &ClipTriangle<V>,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
 ) {
-    let cof_mat = cofactor(model);
-    let triangle = Triangle {
-        v0: *vertices[0],
-        v1: *vertices[1],
-        v2: *vertices[2],
-    };
-    let mut clip_tri = triangle.transform(mvp);
-    clip_tri.v0.normal = (cof_mat * clip_tri.v0.normal.extend(0.0)).xyz();
-    clip_tri.v1.normal = (cof_mat * clip_tri.v1.normal.extend(0.0)).xyz();
-    clip_tri.v2.normal = (cof_mat * clip_tri.v2.normal.extend(0.0)).xyz();
-
-    match clip_cull_triangle(&clip_tri) {
-        ClipResult::None => {}
-        ClipResult::One(tri) => {
-            raster_clipped_triangle(&tri, texture, buffer, z_buffer, viewport_size);
-        }
-        ClipResult::Two(tri) => {
-            raster_clipped_triangle(&tri.0, texture, buffer, z_buffer, viewport_size);
-            raster_clipped_triangle(&tri.1, texture, buffer, z_buffer, viewport_size);
-        }
+    if let Some(setup) = TriangleSetup::new(clip_triangle, target.size()) {
+        raster_triangle_setup(&setup, fragment_shader, settings, &mut target.view());
     }
 }
 
-pub fn raster_mesh(
-    mesh: &Mesh,
-    model: &Mat4,
-    mvp: &Mat4,
-    texture: Option<&Texture>,
-    buffer: &mut Vec<u32>,
-    z_buffer: &mut Vec<f32>,
+// Clips and culls a triangle already processed by the vertex shader,
+// then hands out the setup of every triangle that is left
+pub fn setup_clip_space_triangle<V, F>(
+    clip_tri: &ClipTriangle<V>,
+    settings: &RenderSettings,
     viewport_size: Vec2,
+    mut f: F,
+) where
+    V: Varyings,
+    F: FnMut(TriangleSetup<V>),
+{
+    let clipped = clip_cull_triangle(clip_tri);
+    let clip_debug = settings.clip_debug && matches!(clipped, ClipResult::Polygon(_));
+    let colors = [Vec3::X, Vec3::Y, Vec3::Z];
+    let mut fan_id = 0;
+    clipped.for_each_triangle(|tri| {
+        if let Some(mut setup) = TriangleSetup::new(tri, viewport_size) {
+            if clip_debug {
+                setup.tint = Some(colors[fan_id % colors.len()]);
+            }
+            f(setup);
+        }
+        fan_id += 1;
+    });
+}
+
+// expects a triangle already processed by the vertex shader
+pub fn raster_clip_space_triangle<V: Varyings, F: FragmentShader<V>>(
+    clip_tri: &ClipTriangle<V>,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
 ) {
+    let viewport_size = target.size();
+    let mut view = target.view();
+    setup_clip_space_triangle(clip_tri, settings, viewport_size, |setup| {
+        raster_triangle_setup(&setup, fragment_shader, settings, &mut view);
+    });
+}
+
+pub fn raster_triangle<In, VS, F>(
+    vertices: &[&In; 3],
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+) where
+    VS: VertexShader<In>,
+    F: FragmentShader<VS::Varyings>,
+{
+    let clip_tri = Triangle {
+        v0: vertex_shader.shade_vertex(vertices[0]),
+        v1: vertex_shader.shade_vertex(vertices[1]),
+        v2: vertex_shader.shade_vertex(vertices[2]),
+    };
+    raster_clip_space_triangle(&clip_tri, fragment_shader, settings, target);
+}
+
+pub fn raster_mesh<In, VS, F>(
+    mesh: &Mesh<In>,
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+) where
+    In: Copy,
+    VS: VertexShader<In>,
+    F: FragmentShader<VS::Varyings>,
+{
+    // vertices are shared between triangles, so shade each of them only once
+    let shaded: Vec<ClipVertex<VS::Varyings>> = mesh
+        .vertices()
+        .iter()
+        .map(|vertex| vertex_shader.shade_vertex(vertex))
+        .collect();
     for triangle in mesh.triangles() {
-        let vertices = mesh.get_vertices_from_triangle(*triangle);
-        raster_triangle(
-            &vertices,
-            model,
-            mvp,
-            texture,
-            buffer,
-            z_buffer,
-            viewport_size,
-        );
+        let clip_tri = Triangle {
+            v0: shaded[triangle.x as usize],
+            v1: shaded[triangle.y as usize],
+            v2: shaded[triangle.z as usize],
+        };
+        raster_clip_space_triangle(&clip_tri, fragment_shader, settings, target);
+    }
+}
+
+// Blending only gives the right result when what is behind has already been drawn,
+// so this rasterizes the triangles from the farthest to the closest.
+// Transparent meshes go after the opaque ones, and in far to near order too: the viewer
+// sorts them by the distance of their centers to the eye (Model::center)
+pub fn raster_mesh_sorted<In, VS, F>(
+    mesh: &Mesh<In>,
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+) where
+    In: Copy,
+    VS: VertexShader<In>,
+    F: FragmentShader<VS::Varyings>,
+{
+    let shaded: Vec<ClipVertex<VS::Varyings>> = mesh
+        .vertices()
+        .iter()
+        .map(|vertex| vertex_shader.shade_vertex(vertex))
+        .collect();
+    let mut triangles: Vec<ClipTriangle<VS::Varyings>> = mesh
+        .triangles()
+        .iter()
+        .map(|triangle| Triangle {
+            v0: shaded[triangle.x as usize],
+            v1: shaded[triangle.y as usize],
+            v2: shaded[triangle.z as usize],
+        })
+        .collect();
+    // with a perspective projection clip space w is the distance along the view direction
+    let distance = |tri: &ClipTriangle<VS::Varyings>| {
+        tri.v0.position.w + tri.v1.position.w + tri.v2.position.w
+    };
+    triangles.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+    for clip_tri in &triangles {
+        raster_clip_space_triangle(clip_tri, fragment_shader, settings, target);
     }
 }
 
@@ -176,14 +459,15 @@ pub fn triangle_screen_bounding_box(
 ) -> Option<BoundingBox2D> {
     let bb = get_triangle_bounding_box_2d(positions);
 
-    if bb.left >= viewport_size.x || bb.right < 0.0 || bb.bottom >= viewport_size.y || bb.top < 0.0
+    // top is the smallest y, screen y grows downwards
+    if bb.left >= viewport_size.x || bb.right < 0.0 || bb.top >= viewport_size.y || bb.bottom < 0.0
     {
         None
     } else {
         let left = bb.left.max(0.0);
         let right = bb.right.min(viewport_size.x - 1.0);
-        let bottom = bb.bottom.max(0.0);
-        let top = bb.top.min(viewport_size.y - 1.0);
+        let top = bb.top.max(0.0);
+        let bottom = bb.bottom.min(viewport_size.y - 1.0);
 
         Some(BoundingBox2D {
             left,
@@ -194,14 +478,41 @@ pub fn triangle_screen_bounding_box(
     }
 }
 
-pub enum ClipResult {
+// Planes of the view frustum in homogeneous clip space, a position p is on the
+// inside when plane.dot(p) >= 0. The depth range is [0, w] like glam's projections.
+pub const CLIP_PLANES: [Vec4; 6] = [
+    glam::const_vec4!([1.0, 0.0, 0.0, 1.0]),  // left:   -w <= x
+    glam::const_vec4!([-1.0, 0.0, 0.0, 1.0]), // right:   x <= w
+    glam::const_vec4!([0.0, 1.0, 0.0, 1.0]),  // bottom: -w <= y
+    glam::const_vec4!([0.0, -1.0, 0.0, 1.0]), // top:     y <= w
+    glam::const_vec4!([0.0, 0.0, 1.0, 0.0]),  // near:    0 <= z
+    glam::const_vec4!([0.0, 0.0, -1.0, 1.0]), // far:     z <= w
+];
+
+pub enum ClipResult<V> {
     None,
-    One(Triangle),
-    Two((Triangle, Triangle)),
+    // completely inside, nothing had to be clipped
+    Inside(ClipTriangle<V>),
+    // convex polygon left after clipping, drawn as a triangle fan
+    Polygon(Vec<ClipVertex<V>>),
+}
+
+impl<V: Copy> ClipResult<V> {
+    pub fn for_each_triangle<F: FnMut(&ClipTriangle<V>)>(&self, mut f: F) {
+        match self {
+            ClipResult::None => {}
+            ClipResult::Inside(triangle) => f(triangle),
+            ClipResult::Polygon(polygon) => {
+                for i in 1..polygon.len().saturating_sub(1) {
+                    f(&Triangle::new(polygon[0], polygon[i], polygon[i + 1]));
+                }
+            }
+        }
+    }
 }
 
 //View Frustum Culling
-pub fn cull_triangle_view_frustum(triangle: &Triangle) -> bool {
+pub fn cull_triangle_view_frustum<V>(triangle: &ClipTriangle<V>) -> bool {
     // cull tests against the 6 planes
     if triangle.v0.position.x > triangle.v0.position.w
         && triangle.v1.position.x > triangle.v1.position.w
@@ -241,104 +552,78 @@ pub fn cull_triangle_view_frustum(triangle: &Triangle) -> bool {
     false
 }
 
-pub fn clip_triangle_two(triangle: &Triangle) -> (Triangle, Triangle) {
-    // calculate alpha values for getting adjusted vertices
-    let alpha_a = (-triangle.v0.position.z) / (triangle.v1.position.z - triangle.v0.position.z);
-    let alpha_b = (-triangle.v0.position.z) / (triangle.v2.position.z - triangle.v0.position.z);
-
-    // interpolate to get v0a and v0b
-    let v0_a = lerp(triangle.v0, triangle.v1, alpha_a);
-    let v0_b = lerp(triangle.v0, triangle.v2, alpha_b);
-
-    // draw triangles
-    let mut result_a = *triangle;
-    let mut result_b = *triangle;
-
-    result_a.v0 = v0_a;
-
-    result_b.v0 = v0_a;
-    result_b.v1 = v0_b;
-
-    let green = Vec3::new(0.0, 1.0, 0.0);
-    let blue = Vec3::new(0.0, 0.0, 1.0);
-
-    result_a.v0.color = green;
-    result_a.v1.color = green;
-    result_a.v2.color = green;
-    result_b.v0.color = blue;
-    result_b.v1.color = blue;
-    result_b.v2.color = blue;
-
-    (result_a, result_b)
+// Sutherland-Hodgman against a single plane, the input is a closed polygon
+pub fn clip_polygon_against_plane<V: Varyings>(
+    polygon: &[ClipVertex<V>],
+    plane: Vec4,
+) -> Vec<ClipVertex<V>> {
+    let mut result = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d_current = plane.dot(current.position);
+        let d_next = plane.dot(next.position);
+
+        if d_current >= 0.0 {
+            result.push(*current);
+        }
+        // the edge crosses the plane, emit the intersection
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let alpha = d_current / (d_current - d_next);
+            result.push(lerp(*current, *next, alpha));
+        }
+    }
+    result
 }
 
-pub fn clip_triangle_one(triangle: &Triangle) -> Triangle {
-    // calculate alpha values for getting adjusted vertices
-    let alpha_a = (-triangle.v0.position.z) / (triangle.v2.position.z - triangle.v0.position.z);
-    let alpha_b = (-triangle.v1.position.z) / (triangle.v2.position.z - triangle.v1.position.z);
-
-    // interpolate to get v0a and v0b
-    let mut v0 = lerp(triangle.v0, triangle.v2, alpha_a);
-    let mut v1 = lerp(triangle.v1, triangle.v2, alpha_b);
-
-    let mut v2 = triangle.v2;
-
-    let red = Vec3::new(1.0, 0.0, 0.0);
-
-    v0.color = red;
-    v1.color = red;
-    v2.color = red;
-
-    //println!("out tri: {:?}, {:?}, {:?},", v0, v1, v2);
-    // draw triangles
-    Triangle { v0, v1, v2 }
+pub fn clip_triangle<V: Varyings>(triangle: &ClipTriangle<V>) -> Vec<ClipVertex<V>> {
+    let mut polygon = vec![triangle.v0, triangle.v1, triangle.v2];
+    for plane in CLIP_PLANES {
+        polygon = clip_polygon_against_plane(&polygon, plane);
+        if polygon.len() < 3 {
+            polygon.clear();
+            break;
+        }
+    }
+    polygon
 }
 
-pub fn cull_triangle_backface(triangle: &Triangle) -> bool {
-    let normal = (triangle.v1.position.xyz() - triangle.v0.position.xyz())
-        .cross(triangle.v2.position.xyz() - triangle.v0.position.xyz());
-    // any is vertex valid
-    let view_dir = -Vec3::Z;
-    // also we don't care about normalizing
-    // if negative facing the camera
-    normal.dot(view_dir) >= 0.0
+// Winding test in homogeneous coordinates: the sign of det(xyw) is the orientation
+// of the projected triangle, valid even for vertices behind the camera
+pub fn cull_triangle_backface<V>(triangle: &ClipTriangle<V>) -> bool {
+    let det = glam::Mat3::from_cols(
+        triangle.v0.position.xyw(),
+        triangle.v1.position.xyw(),
+        triangle.v2.position.xyw(),
+    )
+    .determinant();
+    // counter clockwise is front facing
+    det <= 0.0
 }
 
-pub fn clip_cull_triangle(triangle: &Triangle) -> ClipResult {
-    if cull_triangle_backface(triangle) {
+pub fn clip_cull_triangle<V: Varyings>(triangle: &ClipTriangle<V>) -> ClipResult<V> {
+    if cull_triangle_backface(triangle) || cull_triangle_view_frustum(triangle) {
         return ClipResult::None;
     }
-    if cull_triangle_view_frustum(triangle) {
+
+    let vertices = [triangle.v0, triangle.v1, triangle.v2];
+    let inside = CLIP_PLANES
+        .iter()
+        .all(|plane| vertices.iter().all(|v| plane.dot(v.position) >= 0.0));
+    if inside {
+        return ClipResult::Inside(*triangle);
+    }
+
+    let polygon = clip_triangle(triangle);
+    if polygon.is_empty() {
         ClipResult::None
     } else {
-        // clipping routines
-        if triangle.v0.position.z < 0.0 {
-            if triangle.v1.position.z < 0.0 {
-                ClipResult::One(clip_triangle_one(triangle))
-            } else if triangle.v2.position.z < 0.0 {
-                ClipResult::One(clip_triangle_one(&triangle.reorder(VerticesOrder::ACB)))
-            } else {
-                ClipResult::Two(clip_triangle_two(&triangle.reorder(VerticesOrder::ACB)))
-            }
-        } else if triangle.v1.position.z < 0.0 {
-            if triangle.v2.position.z < 0.0 {
-                ClipResult::One(clip_triangle_one(&triangle.reorder(VerticesOrder::BCA)))
-            } else {
-                ClipResult::Two(clip_triangle_two(&triangle.reorder(VerticesOrder::BAC)))
-            }
-        } else if triangle.v2.position.z < 0.0 {
-            ClipResult::Two(clip_triangle_two(&triangle.reorder(VerticesOrder::CBA)))
-        } else {
-            // no near clipping necessary
-            //return original
-            ClipResult::One(*triangle)
-        }
+        ClipResult::Polygon(polygon)
     }
 }
 
-pub fn load_gltf(path: &Path) -> Mesh {
+pub fn load_gltf(path: &Path) -> Result<Mesh, SceneError> {
     // handle loading textures, cameras, meshes here
-    let (document, buffers, _images) = gltf::import(path).unwrap();
+    let (document, buffers, _images) = gltf::import(path)?;
 
     for scene in document.scenes() {
         for node in scene.nodes() {
@@ -358,10 +643,111 @@ pub fn load_gltf(path: &Path) -> Mesh {
                 node.transform().decomposed().2,
             );
             if let Some(mesh) = node.mesh() {
-                return Mesh::load_from_gltf(&mesh, &buffers);
+                return Ok(Mesh::load_from_gltf(&mesh, &buffers)?);
             }
         }
     }
 
-    Mesh::new()
+    Ok(Mesh::new())
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    // the file, or a buffer or image next to it, couldn't be read or parsed
+    Gltf(gltf::Error),
+    // one of the material's textures can't be used
+    Texture(TextureError),
+    // a primitive's accessors don't fit together
+    Mesh(MeshError),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneError::Gltf(error) => write!(f, "can't load glTF: {}", error),
+            SceneError::Texture(error) => error.fmt(f),
+            SceneError::Mesh(error) => write!(f, "bad mesh: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Gltf(error) => Some(error),
+            SceneError::Texture(error) => Some(error),
+            SceneError::Mesh(error) => Some(error),
+        }
+    }
+}
+
+impl From<gltf::Error> for SceneError {
+    fn from(error: gltf::Error) -> Self {
+        SceneError::Gltf(error)
+    }
+}
+
+impl From<TextureError> for SceneError {
+    fn from(error: TextureError) -> Self {
+        SceneError::Texture(error)
+    }
+}
+
+impl From<MeshError> for SceneError {
+    fn from(error: MeshError) -> Self {
+        SceneError::Mesh(error)
+    }
+}
+
+// What a glTF file has to draw and to light it with
+#[derive(Default)]
+pub struct Scene {
+    pub models: Vec<Model>,
+    pub lights: Vec<Light>,
+}
+
+// Every primitive of the meshes in the scene, with its material, and every
+// KHR_lights_punctual light. The whole node hierarchy is walked once: models get the
+// world matrix of their node as their transform, lights are moved by it
+pub fn load_gltf_scene(path: &Path) -> Result<Scene, SceneError> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let mut textures = GltfTextures::new(&images);
+    let mut scene = Scene::default();
+    for gltf_scene in document.scenes() {
+        for node in gltf_scene.nodes() {
+            collect_gltf_node(&node, &Mat4::IDENTITY, &buffers, &mut textures, &mut scene)?;
+        }
+    }
+    Ok(scene)
+}
+
+fn collect_gltf_node(
+    node: &gltf::Node,
+    parent: &Mat4,
+    buffers: &[gltf::buffer::Data],
+    textures: &mut GltfTextures,
+    scene: &mut Scene,
+) -> Result<(), SceneError> {
+    let transform = *parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            scene.models.push(Model {
+                transform,
+                ..Model::load_from_gltf(&primitive, buffers, textures)?
+            });
+        }
+    }
+    if let Some(light) = node.light() {
+        scene.lights.push(Light::from_gltf(&light, &transform));
+    }
+    for child in node.children() {
+        collect_gltf_node(&child, &transform, buffers, textures, scene)?;
+    }
+    Ok(())
+}
+
+// just the models of load_gltf_scene
+pub fn load_gltf_models(path: &Path) -> Result<Vec<Model>, SceneError> {
+    Ok(load_gltf_scene(path)?.models)
 }
@@ -0,0 +1,122 @@
+use glam::{Mat4, Vec3};
+
+// A punctual light, same as glTF's KHR_lights_punctual. Everything is in world
+// space, colors are linear and get multiplied by the intensity
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Light {
+    // infinitely far away, e.g. the sun
+    Directional {
+        // where the light travels, not where it comes from
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    // shines the same in every direction, fading with the square of the distance
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        // past it the light is gone, None for no limit
+        range: Option<f32>,
+    },
+    // a point light limited to a cone
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: Option<f32>,
+        // angles from the axis, full intensity inside the inner one, fading out
+        // towards the outer one
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+impl Light {
+    // The light at `position`: the direction towards the light and the radiance that
+    // arrives, to hand to SurfaceParams::shade
+    pub fn incident(&self, position: Vec3) -> (Vec3, Vec3) {
+        match *self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => (-direction.normalize_or_zero(), color * intensity),
+            Light::Point {
+                position: light_position,
+                color,
+                intensity,
+                range,
+            } => {
+                let (l, attenuation) = distance_attenuation(light_position - position, range);
+                (l, color * intensity * attenuation)
+            }
+            Light::Spot {
+                position: light_position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let (l, attenuation) = distance_attenuation(light_position - position, range);
+                let cos_outer = outer_cone_angle.cos();
+                let cos_inner = inner_cone_angle.cos();
+                let cos_angle = direction.normalize_or_zero().dot(-l);
+                let cone =
+                    ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(1e-4)).clamp(0.0, 1.0);
+                (l, color * intensity * attenuation * cone * cone)
+            }
+        }
+    }
+
+    // `transform` is the world transform of the node holding the light, lights point
+    // down their local -Z
+    pub fn from_gltf(light: &gltf::khr_lights_punctual::Light, transform: &Mat4) -> Self {
+        use gltf::khr_lights_punctual::Kind;
+        let color = Vec3::from(light.color());
+        let intensity = light.intensity();
+        let position = transform.transform_point3(Vec3::ZERO);
+        let direction = transform.transform_vector3(-Vec3::Z).normalize_or_zero();
+        match light.kind() {
+            Kind::Directional => Light::Directional {
+                direction,
+                color,
+                intensity,
+            },
+            Kind::Point => Light::Point {
+                position,
+                color,
+                intensity,
+                range: light.range(),
+            },
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range: light.range(),
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        }
+    }
+}
+
+// Inverse square falloff, with the window KHR_lights_punctual suggests so the light
+// reaches 0 smoothly at its range. Returns the normalized direction too
+fn distance_attenuation(to_light: Vec3, range: Option<f32>) -> (Vec3, f32) {
+    let distance2 = to_light.length_squared().max(1e-8);
+    let mut attenuation = 1.0 / distance2;
+    if let Some(range) = range {
+        let ratio2 = distance2 / (range * range);
+        let window = (1.0 - ratio2 * ratio2).clamp(0.0, 1.0);
+        attenuation *= window * window;
+    }
+    (to_light / distance2.sqrt(), attenuation)
+}
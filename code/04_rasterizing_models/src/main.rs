@@ -1,4 +1,4 @@
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use std::path::Path;
 
 const WIDTH: usize = 500;
@@ -41,12 +41,37 @@ fn main() {
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
     //https://github.com/KhronosGroup/glTF-Sample-Models
-    let texture = Texture::load(Path::new("../../assets/damagedhelmet/Default_albedo.jpg"));
-    let mesh = load_gltf(Path::new("../../assets/damagedhelmet/damagedhelmet.gltf"));
+    // textures come with the materials
+    let path = Path::new("../../assets/damagedhelmet/damagedhelmet.gltf");
+    let scene = load_gltf_scene(path).unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+    let (models, mut lights) = (scene.models, scene.lights);
+    // the helmet comes without lights
+    if lights.is_empty() {
+        lights.push(Light::Directional {
+            direction: -glam::Vec3::ONE.normalize(),
+            color: glam::Vec3::ONE,
+            intensity: std::f32::consts::PI,
+        });
+    }
+
+    // any equirectangular image will do, e.g. from polyhaven.com. Without one the
+    // ambient stays constant
+    let environment = match Texture::load(Path::new("../../assets/environment.hdr")) {
+        Ok(texture) => Some(EnvironmentMap::new(texture)),
+        Err(error) => {
+            eprintln!("No environment map: {}", error);
+            None
+        }
+    };
+
+    // the helmet fits in a sphere of radius ~2 around the origin
+    let mut shadow_maps: Vec<_> = lights
+        .iter()
+        .map(|light| ShadowMap::for_light(light, glam::Vec3::ZERO, 2.0, Default::default()))
+        .collect();
 
-    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
-    let mut z_buffer = vec![f32::INFINITY; WIDTH * HEIGHT];
-    let window_size = glam::vec2(WIDTH as f32, HEIGHT as f32);
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let mut buffer = vec![0; WIDTH * HEIGHT];
 
     let aspect_ratio = WIDTH as f32 / HEIGHT as f32;
 
@@ -58,28 +83,84 @@ fn main() {
         ..Default::default()
     };
 
+    let mut settings = RenderSettings::default();
+    let mut post_process = PostProcess {
+        tone_mapper: ToneMapper::AcesFilmic,
+        ..Default::default()
+    };
+
     let mut rot = std::f32::consts::FRAC_PI_4;
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        clear_buffer(&mut buffer, 0);
-        clear_buffer(&mut z_buffer, f32::INFINITY);
+        target.clear(glam::Vec4::ZERO);
         process_input_camera(&window, &mut camera);
+        if window.is_key_pressed(Key::C, KeyRepeat::No) {
+            settings.clip_debug = !settings.clip_debug;
+        }
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            post_process.tone_mapper = post_process.tone_mapper.next();
+        }
+        if window.is_key_pressed(Key::E, KeyRepeat::No) {
+            post_process.auto_exposure = !post_process.auto_exposure;
+        }
+        if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) {
+            post_process.exposure += 0.25;
+        }
+        if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) {
+            post_process.exposure -= 0.25;
+        }
 
         let parent_local =
             Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, rot, 0.0, 0.0))
                 .local();
+        for shadow_map in shadow_maps.iter_mut().flatten() {
+            shadow_map.clear();
+            for model in &models {
+                shadow_map.render_model(model, &parent_local);
+            }
+        }
+
         let view = camera.view();
         let proj = camera.projection();
 
-        raster_mesh(
-            &mesh,
-            &parent_local,
-            &(proj * view * parent_local),
-            Some(&texture),
-            &mut buffer,
-            &mut z_buffer,
-            window_size,
-        );
+        let view_proj = proj * view;
+        let eye = camera.transform.translation;
+        for model in models.iter().filter(|model| !model.is_transparent()) {
+            let settings = model.render_settings(&settings);
+            let model_matrix = parent_local * model.transform;
+            let shader = PbrShader {
+                shadow_maps: &shadow_maps,
+                environment: environment.as_ref(),
+                ..PbrShader::new(&model_matrix, &view_proj, eye, &model.material, &lights)
+            };
+            raster_mesh_tiled(&model.mesh, &shader, &shader, &settings, &mut target);
+        }
+        // whatever is still empty shows the environment, black without one
+        if let Some(environment) = &environment {
+            Background::from_environment(environment).draw(&view_proj, f32::INFINITY, &mut target);
+        }
+        // transparent ones last, so there is something to blend with, farthest first
+        let distance = |model: &Model| {
+            let center = (parent_local * model.transform).transform_point3(model.center());
+            (center - eye).length()
+        };
+        let mut transparent: Vec<_> = models
+            .iter()
+            .filter(|model| model.is_transparent())
+            .collect();
+        transparent.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+        for model in transparent {
+            let settings = model.render_settings(&settings);
+            let model_matrix = parent_local * model.transform;
+            let shader = PbrShader {
+                shadow_maps: &shadow_maps,
+                environment: environment.as_ref(),
+                ..PbrShader::new(&model_matrix, &view_proj, eye, &model.material, &lights)
+            };
+            raster_mesh_sorted(&model.mesh, &shader, &shader, &settings, &mut target);
+        }
         rot += 0.05;
+        post_process.apply(&mut target);
+        target.present(&mut buffer);
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
     }
 }
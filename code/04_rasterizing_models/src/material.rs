@@ -0,0 +1,240 @@
+use crate::{
+    sampler::Sampler,
+    texture::{ColorSpace, Texture, TextureError},
+};
+use glam::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// How the alpha of a surface is interpreted, same as glTF's alphaMode
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum AlphaMode {
+    // alpha is ignored
+    #[default]
+    Opaque,
+    // cutout: fully opaque where alpha >= cutoff, discarded everywhere else
+    Mask {
+        cutoff: f32,
+    },
+    // alpha blended over what is behind, has to be drawn after the opaque surfaces
+    // and back to front (see raster_mesh_sorted)
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                // the spec default
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        }
+    }
+}
+
+// A texture and how it is read. Textures are shared, several materials often use
+// the same image
+#[derive(Clone)]
+pub struct MaterialTexture {
+    pub texture: Arc<Texture>,
+    pub sampler: Sampler,
+}
+
+impl MaterialTexture {
+    pub fn new(texture: Arc<Texture>) -> Self {
+        Self {
+            texture,
+            sampler: Sampler::default(),
+        }
+    }
+
+    // linear rgba, see Sampler::sample
+    pub fn sample(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        self.sampler.sample(&self.texture, uv, ddx, ddy)
+    }
+}
+
+// glTF's metallic-roughness material. Every texture is multiplied by its factor,
+// a missing texture counts as white
+#[derive(Clone)]
+pub struct Material {
+    // linear rgba
+    pub base_color_factor: Vec4,
+    pub base_color_texture: Option<MaterialTexture>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // roughness in green, metalness in blue
+    pub metallic_roughness_texture: Option<MaterialTexture>,
+    // tangent space, scale multiplies x and y
+    pub normal_texture: Option<MaterialTexture>,
+    pub normal_scale: f32,
+    // how much ambient light reaches the surface, in red. 0 strength turns it off
+    pub occlusion_texture: Option<MaterialTexture>,
+    pub occlusion_strength: f32,
+    // linear rgb, light given off by the surface itself
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<MaterialTexture>,
+    pub alpha_mode: AlphaMode,
+}
+
+// same as a glTF material with nothing set
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color_factor: Vec4::ONE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: Vec3::ZERO,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+}
+
+impl Material {
+    // just a base color texture
+    pub fn from_texture(texture: Arc<Texture>) -> Self {
+        Self {
+            base_color_texture: Some(MaterialTexture::new(texture)),
+            ..Default::default()
+        }
+    }
+
+    pub fn load_from_gltf(
+        material: &gltf::Material,
+        textures: &mut GltfTextures,
+    ) -> Result<Self, TextureError> {
+        let pbr = material.pbr_metallic_roughness();
+        // colors are sRGB encoded, everything else is data
+        let mut load = |texture: Option<gltf::Texture>, color_space| match texture {
+            Some(texture) => textures.load(&texture, color_space).map(Some),
+            None => Ok(None),
+        };
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        Ok(Self {
+            base_color_factor: Vec4::from(pbr.base_color_factor()),
+            base_color_texture: load(
+                pbr.base_color_texture().map(|info| info.texture()),
+                ColorSpace::Srgb,
+            )?,
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: load(
+                pbr.metallic_roughness_texture().map(|info| info.texture()),
+                ColorSpace::Linear,
+            )?,
+            normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+            normal_texture: load(normal.map(|normal| normal.texture()), ColorSpace::Linear)?,
+            occlusion_strength: occlusion
+                .as_ref()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            occlusion_texture: load(
+                occlusion.map(|occlusion| occlusion.texture()),
+                ColorSpace::Linear,
+            )?,
+            emissive_factor: Vec3::from(material.emissive_factor()),
+            emissive_texture: load(
+                material.emissive_texture().map(|info| info.texture()),
+                ColorSpace::Srgb,
+            )?,
+            alpha_mode: AlphaMode::from_gltf(material),
+        })
+    }
+
+    // Base color times the texture, if there is one
+    pub fn base_color(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        match &self.base_color_texture {
+            Some(texture) => self.base_color_factor * texture.sample(uv, ddx, ddy),
+            None => self.base_color_factor,
+        }
+    }
+
+    // (metallic, roughness)
+    pub fn metallic_roughness(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> (f32, f32) {
+        match &self.metallic_roughness_texture {
+            Some(texture) => {
+                let texel = texture.sample(uv, ddx, ddy);
+                (
+                    self.metallic_factor * texel.z,
+                    self.roughness_factor * texel.y,
+                )
+            }
+            None => (self.metallic_factor, self.roughness_factor),
+        }
+    }
+
+    // Tangent space normal, +Z without a normal map. The texture stores it in 0..1,
+    // x and y get scaled by normal_scale
+    pub fn normal(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec3 {
+        match &self.normal_texture {
+            Some(texture) => {
+                let texel = texture.sample(uv, ddx, ddy).truncate() * 2.0 - Vec3::ONE;
+                (texel * glam::vec3(self.normal_scale, self.normal_scale, 1.0)).normalize_or_zero()
+            }
+            None => Vec3::Z,
+        }
+    }
+
+    // 1 where nothing blocks the ambient light
+    pub fn occlusion(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> f32 {
+        match &self.occlusion_texture {
+            Some(texture) => {
+                let occlusion = texture.sample(uv, ddx, ddy).x;
+                1.0 + self.occlusion_strength * (occlusion - 1.0)
+            }
+            None => 1.0,
+        }
+    }
+
+    pub fn emissive(&self, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec3 {
+        match &self.emissive_texture {
+            Some(texture) => self.emissive_factor * texture.sample(uv, ddx, ddy).truncate(),
+            None => self.emissive_factor,
+        }
+    }
+}
+
+// Turns the images of a glTF file into textures as materials ask for them, every
+// image gets converted once per color space it is used with
+pub struct GltfTextures<'a> {
+    images: &'a [gltf::image::Data],
+    loaded: HashMap<(usize, ColorSpace), Arc<Texture>>,
+}
+
+impl<'a> GltfTextures<'a> {
+    pub fn new(images: &'a [gltf::image::Data]) -> Self {
+        Self {
+            images,
+            loaded: HashMap::new(),
+        }
+    }
+
+    pub fn load(
+        &mut self,
+        texture: &gltf::Texture,
+        color_space: ColorSpace,
+    ) -> Result<MaterialTexture, TextureError> {
+        let index = texture.source().index();
+        let texture_data = match self.loaded.get(&(index, color_space)) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let loaded = Arc::new(Texture::from_gltf_image(&self.images[index], color_space)?);
+                self.loaded.insert((index, color_space), loaded.clone());
+                loaded
+            }
+        };
+        Ok(MaterialTexture {
+            texture: texture_data,
+            sampler: Sampler::from_gltf(&texture.sampler()),
+        })
+    }
+}
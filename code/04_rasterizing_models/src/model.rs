@@ -0,0 +1,62 @@
+use crate::{
+    geometry::Mesh,
+    material::{AlphaMode, GltfTextures, Material},
+    render_settings::{BlendMode, RenderSettings},
+    SceneError,
+};
+use glam::{Mat4, Vec3, Vec4Swizzles};
+
+// A glTF primitive: a mesh plus what is needed to know how to draw it
+#[derive(Default)]
+pub struct Model {
+    pub mesh: Mesh,
+    pub material: Material,
+    // from the mesh's space to the scene's: the world matrix of the glTF node
+    pub transform: Mat4,
+}
+
+impl Model {
+    pub fn load_from_gltf(
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        textures: &mut GltfTextures,
+    ) -> Result<Self, SceneError> {
+        Ok(Self {
+            mesh: Mesh::load_from_gltf_primitive(primitive, buffers)?,
+            material: Material::load_from_gltf(&primitive.material(), textures)?,
+            transform: Mat4::IDENTITY,
+        })
+    }
+
+    // Middle of the mesh's bounding box, in model space. What transparent models are
+    // sorted by, see raster_mesh_sorted
+    pub fn center(&self) -> Vec3 {
+        let mut vertices = self.mesh.vertices().iter().map(|v| v.position.xyz());
+        let first = match vertices.next() {
+            Some(first) => first,
+            None => return Vec3::ZERO,
+        };
+        let (min, max) = vertices.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+        (min + max) * 0.5
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.material.alpha_mode == AlphaMode::Blend
+    }
+
+    // Pipeline state to draw this model with, everything else is taken from `settings`.
+    // Transparent surfaces are still depth tested against the opaque ones, but don't
+    // write depth, so they can't hide each other
+    pub fn render_settings(&self, settings: &RenderSettings) -> RenderSettings {
+        let mut settings = *settings;
+        match self.material.alpha_mode {
+            AlphaMode::Opaque => {}
+            AlphaMode::Mask { cutoff } => settings.alpha_cutoff = Some(cutoff),
+            AlphaMode::Blend => {
+                settings.blend = BlendMode::Alpha;
+                settings.depth.write = false;
+            }
+        }
+        settings
+    }
+}
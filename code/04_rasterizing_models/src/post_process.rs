@@ -0,0 +1,124 @@
+use crate::render_target::RenderTarget;
+use glam::{Vec3, Vec4Swizzles};
+
+// Squeezes linear colors that can go over 1 back into [0, 1] before they get encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ToneMapper {
+    // anything over 1 just saturates
+    #[default]
+    Clamp,
+    // x / (1 + x), never quite reaches white
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve, a bit more contrast and
+    // blown out highlights
+    AcesFilmic,
+    // Hajime Uchimura's curve from Gran Turismo: a toe, a linear middle part left
+    // untouched and a smooth shoulder up to white
+    Uchimura,
+}
+
+impl ToneMapper {
+    pub fn apply(self, color: Vec3) -> Vec3 {
+        match self {
+            ToneMapper::Clamp => color.clamp(Vec3::ZERO, Vec3::ONE),
+            ToneMapper::Reinhard => color / (color + 1.0),
+            ToneMapper::AcesFilmic => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                let mapped = (color * (color * a + b)) / (color * (color * c + d) + e);
+                mapped.clamp(Vec3::ZERO, Vec3::ONE)
+            }
+            ToneMapper::Uchimura => {
+                glam::vec3(uchimura(color.x), uchimura(color.y), uchimura(color.z))
+            }
+        }
+    }
+
+    // to switch between them at runtime
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapper::Clamp => ToneMapper::Reinhard,
+            ToneMapper::Reinhard => ToneMapper::AcesFilmic,
+            ToneMapper::AcesFilmic => ToneMapper::Uchimura,
+            ToneMapper::Uchimura => ToneMapper::Clamp,
+        }
+    }
+}
+
+// with the default parameters from the talk
+fn uchimura(x: f32) -> f32 {
+    let x = x.max(0.0);
+    // max brightness, contrast, linear section start and length, black tightness
+    let (p, a, m, l, c) = (1.0f32, 1.0f32, 0.22f32, 0.4f32, 1.33f32);
+
+    let l0 = ((p - m) * l) / a;
+    let s0 = m + l0;
+    let s1 = m + a * l0;
+    let c2 = (a * p) / (p - s1);
+    let cp = -c2 / p;
+
+    let smoothstep = |t: f32| {
+        let t = (t / m).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let w0 = 1.0 - smoothstep(x);
+    let w2 = if x >= s0 { 1.0 } else { 0.0 };
+    let w1 = 1.0 - w0 - w2;
+
+    let toe = m * (x / m).powf(c);
+    let linear = m + a * (x - m);
+    let shoulder = p - (p - s1) * (cp * (x - s0)).exp();
+    toe * w0 + linear * w1 + shoulder * w2
+}
+
+// Relative luminance of a linear color (Rec. 709 primaries)
+pub fn luminance(color: Vec3) -> f32 {
+    color.dot(glam::vec3(0.2126, 0.7152, 0.0722))
+}
+
+// What happens to the frame between rasterizing and presenting it
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PostProcess {
+    pub tone_mapper: ToneMapper,
+    // in stops, every +1 doubles the light. Added on top of the auto exposure
+    pub exposure: f32,
+    // scale the frame so its average luminance ends up at middle gray
+    pub auto_exposure: bool,
+}
+
+// the luminance auto exposure brings the average to
+pub const MIDDLE_GRAY: f32 = 0.18;
+
+impl PostProcess {
+    // what colors get multiplied by before tone mapping
+    pub fn exposure_scale(&self, target: &RenderTarget) -> f32 {
+        let mut scale = self.exposure.exp2();
+        if self.auto_exposure {
+            scale *= MIDDLE_GRAY / average_luminance(target).max(1e-4);
+        }
+        scale
+    }
+
+    // Works in place on the linear color of the target, alpha is left alone.
+    // Goes right before RenderTarget::present
+    pub fn apply(&self, target: &mut RenderTarget) {
+        let scale = self.exposure_scale(target);
+        for color in target.color.iter_mut() {
+            let mapped = self.tone_mapper.apply(color.xyz() * scale);
+            *color = mapped.extend(color.w);
+        }
+    }
+}
+
+// Geometric mean of the luminance, so a few very bright pixels (e.g. the sun) don't
+// drag the whole frame into the dark
+pub fn average_luminance(target: &RenderTarget) -> f32 {
+    // keeps black pixels from taking the log to -infinity
+    let delta = 1e-4;
+    // summed in f64, there are a lot of pixels
+    let sum: f64 = target
+        .color
+        .iter()
+        .map(|color| (delta + luminance(color.xyz()).max(0.0) as f64).ln())
+        .sum();
+    (sum / target.color.len().max(1) as f64).exp() as f32
+}
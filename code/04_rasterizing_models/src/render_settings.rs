@@ -0,0 +1,125 @@
+use glam::{Vec3, Vec4, Vec4Swizzles};
+
+// Switches that change how the rasterizer behaves, independent from the shaders
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RenderSettings {
+    // tint triangles produced by clipping: red, green, blue along the fan
+    pub clip_debug: bool,
+    pub depth: DepthState,
+    pub blend: BlendMode,
+    // alpha test: fragments with an alpha below the cutoff are discarded, the others
+    // are made fully opaque. Discarded fragments write neither color nor depth
+    pub alpha_cutoff: Option<f32>,
+    // Only depth gets tested and written, e.g. for shadow maps. Without an alpha cutoff
+    // the fragment shader doesn't run at all, and the target needs no color buffer
+    // (see RenderTarget::depth_only)
+    pub depth_only: bool,
+}
+
+// How a shaded fragment gets combined with the color already in the target.
+// Both are linear rgba, colors can go over 1 but alpha stays in [0, 1]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    // the fragment replaces whatever was there
+    #[default]
+    Opaque,
+    // classic "over": the fragment's alpha says how much of it covers the target
+    Alpha,
+    // the fragment color is already multiplied by its alpha
+    PremultipliedAlpha,
+    // light adds up, e.g. for glows and particles
+    Additive,
+    // darkens the target, e.g. for stained glass or baked shadows
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn blend(self, src: Vec4, dst: Vec4) -> Vec4 {
+        let alpha = src.w;
+        let result = match self {
+            BlendMode::Opaque => src,
+            BlendMode::Alpha => {
+                let color = src.xyz() * alpha + dst.xyz() * (1.0 - alpha);
+                color.extend(alpha + dst.w * (1.0 - alpha))
+            }
+            BlendMode::PremultipliedAlpha => {
+                let color = src.xyz() + dst.xyz() * (1.0 - alpha);
+                color.extend(alpha + dst.w * (1.0 - alpha))
+            }
+            BlendMode::Additive => (dst.xyz() + src.xyz() * alpha).extend(dst.w),
+            BlendMode::Multiply => (dst.xyz() * src.xyz()).extend(dst.w),
+        };
+        result
+            .xyz()
+            .max(Vec3::ZERO)
+            .extend(result.w.clamp(0.0, 1.0))
+    }
+}
+
+// How a fragment's depth is compared against the one already in the depth buffer,
+// the fragment passes when `fragment_depth <op> stored_depth` holds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepthCompare {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+
+impl DepthCompare {
+    pub fn test(self, depth: f32, stored: f32) -> bool {
+        match self {
+            DepthCompare::Never => false,
+            DepthCompare::Less => depth < stored,
+            DepthCompare::LessEqual => depth <= stored,
+            DepthCompare::Equal => depth == stored,
+            DepthCompare::NotEqual => depth != stored,
+            DepthCompare::GreaterEqual => depth >= stored,
+            DepthCompare::Greater => depth > stored,
+            DepthCompare::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DepthState {
+    pub compare: DepthCompare,
+    // when false fragments are still tested, but the depth buffer is left untouched
+    // (e.g. for decals and transparent surfaces)
+    pub write: bool,
+    // the [0, 1] depth coming out of the projection gets remapped to [near, far],
+    // e.g. a range of (0.0, 0.1) keeps an overlay in front of everything else
+    pub range_near: f32,
+    pub range_far: f32,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            compare: DepthCompare::Less,
+            write: true,
+            range_near: 0.0,
+            range_far: 1.0,
+        }
+    }
+}
+
+impl DepthState {
+    // Goes with Camera::reversed_z: near maps to 1 and far to 0, so closer is greater.
+    // Floats are much denser around 0, which keeps far away surfaces from fighting.
+    // The depth buffer has to be cleared to 0 (see RenderTarget::clear_with_depth)
+    pub fn reversed_z() -> Self {
+        Self {
+            compare: DepthCompare::Greater,
+            ..Default::default()
+        }
+    }
+
+    pub fn map_to_range(&self, depth: f32) -> f32 {
+        self.range_near + (self.range_far - self.range_near) * depth
+    }
+}
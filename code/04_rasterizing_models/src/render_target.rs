@@ -0,0 +1,169 @@
+use crate::utils::*;
+use glam::{Vec2, Vec4};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Owns everything the rasterizer writes into, so we can render without a window
+// (e.g. on a CI machine) and dump the result to disk
+pub struct RenderTarget {
+    pub width: usize,
+    pub height: usize,
+    // linear rgba, can go over 1. Only gets encoded to sRGB when it leaves the target
+    // (see present)
+    pub color: Vec<Vec4>,
+    pub depth: Vec<f32>,
+}
+
+impl RenderTarget {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            color: vec![Vec4::ZERO; width * height],
+            depth: vec![f32::INFINITY; width * height],
+        }
+    }
+
+    // No color buffer, only depth: for RenderSettings::depth_only passes (e.g. shadow
+    // maps), anything else drawn into it panics
+    pub fn depth_only(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            color: Vec::new(),
+            depth: vec![f32::INFINITY; width * height],
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        glam::vec2(self.width as f32, self.height as f32)
+    }
+
+    // view covering the whole target
+    pub fn view(&mut self) -> TargetView<'_> {
+        TargetView {
+            left: 0,
+            top: 0,
+            width: self.width,
+            height: self.height,
+            color: &mut self.color,
+            depth: &mut self.depth,
+        }
+    }
+
+    // copies a rectangle out, so it can be rendered to on its own (e.g. on another thread)
+    pub fn read_tile(&self, left: usize, top: usize, width: usize, height: usize) -> Tile {
+        let mut color = Vec::with_capacity(width * height);
+        let mut depth = Vec::with_capacity(width * height);
+        for y in top..top + height {
+            let row = coords_to_index(left, y, self.width);
+            // tiles of a depth only target have no color either
+            if !self.color.is_empty() {
+                color.extend_from_slice(&self.color[row..row + width]);
+            }
+            depth.extend_from_slice(&self.depth[row..row + width]);
+        }
+        Tile {
+            left,
+            top,
+            width,
+            height,
+            color,
+            depth,
+        }
+    }
+
+    pub fn write_tile(&mut self, tile: &Tile) {
+        for y in 0..tile.height {
+            let row = coords_to_index(tile.left, tile.top + y, self.width);
+            let tile_row = coords_to_index(0, y, tile.width);
+            if !self.color.is_empty() {
+                self.color[row..row + tile.width]
+                    .copy_from_slice(&tile.color[tile_row..tile_row + tile.width]);
+            }
+            self.depth[row..row + tile.width]
+                .copy_from_slice(&tile.depth[tile_row..tile_row + tile.width]);
+        }
+    }
+
+    pub fn clear(&mut self, color: Vec4) {
+        self.clear_with_depth(color, f32::INFINITY);
+    }
+
+    // with reversed-Z (or any compare other than less) depth has to start elsewhere
+    pub fn clear_with_depth(&mut self, color: Vec4, depth: f32) {
+        clear_buffer(&mut self.color, color);
+        clear_buffer(&mut self.depth, depth);
+    }
+
+    // Encodes the color to sRGB argb, what a window expects, `buffer` has to be as big
+    // as the target
+    pub fn present(&self, buffer: &mut [u32]) {
+        for (argb, color) in buffer.iter_mut().zip(&self.color) {
+            *argb = encode_srgb(*color);
+        }
+    }
+
+    // alpha is dropped on export, same as when the buffer is shown in a window
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.color.len() * 3);
+        for color in &self.color {
+            let (_, r, g, b) = from_argb8(encode_srgb(*color));
+            rgb.extend_from_slice(&[r, g, b]);
+        }
+        rgb
+    }
+
+    // binary PPM (P6), no dependencies needed
+    pub fn save_ppm(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.to_rgb8())?;
+        writer.flush()
+    }
+
+    pub fn save_png(&self, path: &Path) -> std::io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb8())?;
+        Ok(())
+    }
+}
+
+// A rectangle of the screen that the rasterizer can write to,
+// screen pixel (x, y) is stored at (x - left) + (y - top) * width
+pub struct TargetView<'a> {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+    pub color: &'a mut [Vec4],
+    pub depth: &'a mut [f32],
+}
+
+// Owned copy of a rectangle of a render target
+pub struct Tile {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Vec4>,
+    pub depth: Vec<f32>,
+}
+
+impl Tile {
+    pub fn view(&mut self) -> TargetView<'_> {
+        TargetView {
+            left: self.left,
+            top: self.top,
+            width: self.width,
+            height: self.height,
+            color: &mut self.color,
+            depth: &mut self.depth,
+        }
+    }
+}
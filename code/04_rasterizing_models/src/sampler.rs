@@ -0,0 +1,279 @@
+use crate::{texture::Texture, utils::lerp};
+use glam::{Vec2, Vec4};
+
+// Same limit as most GPUs, every sample is a full trilinear lookup
+pub const MAX_ANISOTROPY: f32 = 16.0;
+
+// Below 1 (or NaN) means no anisotropy, and anything above the limit would take
+// forever on a grazing footprint. Not f32::clamp, that one keeps NaN
+fn clamp_anisotropy(max_anisotropy: f32) -> f32 {
+    if max_anisotropy >= 1.0 {
+        max_anisotropy.min(MAX_ANISOTROPY)
+    } else {
+        1.0
+    }
+}
+
+// The usual combinations of texel and mip filtering, see Sampler::new
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    // closest texel of the closest mip level
+    Nearest,
+    // 2x2 texels of the closest mip level
+    Bilinear,
+    // bilinear on the two closest mip levels, blended by how close each one is
+    Trilinear,
+}
+
+// How texels are read within one mip level
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TexelFilter {
+    // the closest one
+    Nearest,
+    // the 2x2 around, weighted by distance
+    Linear,
+}
+
+// Which mip levels a minified texture is read from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MipmapMode {
+    // always the full size image
+    None,
+    // the closest level
+    Nearest,
+    // the two closest levels, blended
+    Linear,
+}
+
+// What happens to texture coordinates outside of [0, 1], same as glTF's wrapS/wrapT
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressMode {
+    Repeat,
+    // every other repetition is flipped, so there are no seams
+    MirroredRepeat,
+    // the border texels stretch out forever
+    ClampToEdge,
+}
+
+impl AddressMode {
+    pub fn from_gltf(mode: gltf::texture::WrappingMode) -> Self {
+        match mode {
+            gltf::texture::WrappingMode::Repeat => AddressMode::Repeat,
+            gltf::texture::WrappingMode::MirroredRepeat => AddressMode::MirroredRepeat,
+            gltf::texture::WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        }
+    }
+
+    // maps any texel coordinate, negative ones too, inside [0, size)
+    pub fn apply(self, coord: i64, size: usize) -> usize {
+        let size = size as i64;
+        let coord = match self {
+            AddressMode::Repeat => coord.rem_euclid(size),
+            AddressMode::MirroredRepeat => {
+                let coord = coord.rem_euclid(2 * size);
+                if coord < size {
+                    coord
+                } else {
+                    2 * size - 1 - coord
+                }
+            }
+            AddressMode::ClampToEdge => coord.clamp(0, size - 1),
+        };
+        coord as usize
+    }
+}
+
+// How a texture gets read: which texels, and how they get combined
+#[derive(Debug, Copy, Clone)]
+pub struct Sampler {
+    // when a texel covers less than a pixel (lod above 0)
+    pub min_filter: TexelFilter,
+    // when a texel covers a pixel or more
+    pub mag_filter: TexelFilter,
+    pub mipmap: MipmapMode,
+    // along u and v
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    // most samples taken along a stretched pixel footprint, 1 turns anisotropic
+    // filtering off (see sample). Never more than MAX_ANISOTROPY
+    pub max_anisotropy: f32,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(Filter::Trilinear)
+    }
+}
+
+impl Sampler {
+    pub fn new(filter: Filter) -> Self {
+        let (texels, mipmap) = match filter {
+            Filter::Nearest => (TexelFilter::Nearest, MipmapMode::Nearest),
+            Filter::Bilinear => (TexelFilter::Linear, MipmapMode::Nearest),
+            Filter::Trilinear => (TexelFilter::Linear, MipmapMode::Linear),
+        };
+        Self {
+            min_filter: texels,
+            mag_filter: texels,
+            mipmap,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Repeat,
+            max_anisotropy: 1.0,
+        }
+    }
+
+    pub fn with_address_mode(mut self, address_u: AddressMode, address_v: AddressMode) -> Self {
+        self.address_u = address_u;
+        self.address_v = address_v;
+        self
+    }
+
+    pub fn with_max_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = clamp_anisotropy(max_anisotropy);
+        self
+    }
+
+    // Same filters as OpenGL. Without them in the file we pick trilinear
+    pub fn from_gltf(sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter};
+        let (min_filter, mipmap) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (TexelFilter::Nearest, MipmapMode::None),
+            Some(MinFilter::Linear) => (TexelFilter::Linear, MipmapMode::None),
+            Some(MinFilter::NearestMipmapNearest) => (TexelFilter::Nearest, MipmapMode::Nearest),
+            Some(MinFilter::LinearMipmapNearest) => (TexelFilter::Linear, MipmapMode::Nearest),
+            Some(MinFilter::NearestMipmapLinear) => (TexelFilter::Nearest, MipmapMode::Linear),
+            Some(MinFilter::LinearMipmapLinear) | None => (TexelFilter::Linear, MipmapMode::Linear),
+        };
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => TexelFilter::Nearest,
+            Some(MagFilter::Linear) | None => TexelFilter::Linear,
+        };
+        Self {
+            min_filter,
+            mag_filter,
+            mipmap,
+            ..Default::default()
+        }
+        .with_address_mode(
+            AddressMode::from_gltf(sampler.wrap_s()),
+            AddressMode::from_gltf(sampler.wrap_t()),
+        )
+    }
+
+    // Returns rgba. ddx and ddy are how much uv changes from one pixel to the next
+    // (see Fragment), they tell how many texels end up in a pixel.
+    // A pixel footprint stretched along one axis (e.g. a floor seen at a grazing angle)
+    // would pick a mip level as blurry as its longest side. With anisotropic filtering
+    // the level is picked from the shortest side instead, and several samples are
+    // spread along the longest one
+    pub fn sample(&self, texture: &Texture, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        let (axis, samples) = self.anisotropy(texture, ddx, ddy);
+        if samples == 1 {
+            let lod = self.level_of_detail(texture, ddx, ddy);
+            return self.sample_level(texture, lod, uv);
+        }
+
+        // every sample covers an equal piece of the footprint, and gets to see about
+        // as many texels along the axis as along the shortest side
+        let minor = axis / samples as f32;
+        let lod = self.level_of_detail(texture, minor, minor);
+        let mut sum = Vec4::ZERO;
+        for i in 0..samples {
+            let offset = (i as f32 + 0.5) / samples as f32 - 0.5;
+            sum += self.sample_level(texture, lod, uv + axis * offset);
+        }
+        sum / samples as f32
+    }
+
+    // The longest side of the footprint, in uv, and how many samples it needs
+    pub fn anisotropy(&self, texture: &Texture, ddx: Vec2, ddy: Vec2) -> (Vec2, usize) {
+        let size = glam::vec2(texture.width as f32, texture.height as f32);
+        let (x, y) = ((ddx * size).length(), (ddy * size).length());
+        let (axis, major, minor) = if x > y { (ddx, x, y) } else { (ddy, y, x) };
+        // the field is public, so it may not have gone through with_max_anisotropy
+        let max = clamp_anisotropy(self.max_anisotropy);
+        let ratio = (major / minor.max(f32::MIN_POSITIVE)).clamp(1.0, max);
+        (axis, (ratio.ceil() as usize).max(1))
+    }
+
+    // lod as given by level_of_detail, 0 and below is magnification
+    pub fn sample_level(&self, texture: &Texture, lod: f32, uv: Vec2) -> Vec4 {
+        if lod <= 0.0 {
+            return self.filter_texels(self.mag_filter, texture, 0, uv);
+        }
+        let filter = self.min_filter;
+        match self.mipmap {
+            MipmapMode::None => self.filter_texels(filter, texture, 0, uv),
+            MipmapMode::Nearest => self.filter_texels(filter, texture, lod.round() as usize, uv),
+            MipmapMode::Linear => {
+                let level = lod.floor() as usize;
+                let fine = self.filter_texels(filter, texture, level, uv);
+                if level + 1 == texture.level_count() {
+                    return fine;
+                }
+                let coarse = self.filter_texels(filter, texture, level + 1, uv);
+                lerp(fine, coarse, lod.fract())
+            }
+        }
+    }
+
+    fn filter_texels(
+        &self,
+        filter: TexelFilter,
+        texture: &Texture,
+        level: usize,
+        uv: Vec2,
+    ) -> Vec4 {
+        match filter {
+            TexelFilter::Nearest => self.nearest(texture, level, uv),
+            TexelFilter::Linear => self.bilinear(texture, level, uv),
+        }
+    }
+
+    // Fractional mip level: 0 when a pixel covers one texel of the full size image,
+    // 1 when it covers 2x2 of them, and so on
+    pub fn level_of_detail(&self, texture: &Texture, ddx: Vec2, ddy: Vec2) -> f32 {
+        let size = glam::vec2(texture.width as f32, texture.height as f32);
+        let footprint = (ddx * size).length().max((ddy * size).length());
+        // below one texel per pixel log2 is negative, magnifying uses the full size image
+        let max_level = (texture.level_count() - 1) as f32;
+        footprint.log2().clamp(0.0, max_level)
+    }
+
+    pub fn nearest(&self, texture: &Texture, level: usize, uv: Vec2) -> Vec4 {
+        let (width, height) = texture.level_size(level);
+        let x = (uv.x * width as f32).floor() as i64;
+        let y = (uv.y * height as f32).floor() as i64;
+        let x = self.address_u.apply(x, width);
+        let y = self.address_v.apply(y, height);
+        texture.texel(level, x, y)
+    }
+
+    pub fn bilinear(&self, texture: &Texture, level: usize, uv: Vec2) -> Vec4 {
+        let (width, height) = texture.level_size(level);
+        // texel centers are at half coordinates
+        let p = uv * glam::vec2(width as f32, height as f32) - 0.5;
+        let (x, y) = (p.x.floor() as i64, p.y.floor() as i64);
+        let t = p - p.floor();
+
+        let (x0, x1) = (
+            self.address_u.apply(x, width),
+            self.address_u.apply(x + 1, width),
+        );
+        let (y0, y1) = (
+            self.address_v.apply(y, height),
+            self.address_v.apply(y + 1, height),
+        );
+        let top = lerp(
+            texture.texel(level, x0, y0),
+            texture.texel(level, x1, y0),
+            t.x,
+        );
+        let bottom = lerp(
+            texture.texel(level, x0, y1),
+            texture.texel(level, x1, y1),
+            t.x,
+        );
+        lerp(top, bottom, t.y)
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    brdf::SurfaceParams,
+    environment::EnvironmentMap,
+    geometry::Vertex,
+    light::Light,
+    material::Material,
+    shadow::ShadowMap,
+    utils::cofactor,
+    varyings::{ClipVertex, Varyings},
+};
+use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+// What the rasterizer hands to the fragment shader for every covered pixel
+#[derive(Debug, Copy, Clone)]
+pub struct Fragment<V> {
+    // pixel center in screen space
+    pub coords: Vec2,
+    pub depth: f32,
+    // vertex shader outputs, perspective correct interpolated
+    pub varyings: V,
+    // how much the varyings change moving one pixel right and one pixel down,
+    // e.g. to know how big the pixel footprint in a texture is
+    pub ddx: V,
+    pub ddy: V,
+}
+
+// Input is the vertex type stored in the mesh,
+// Varyings is what gets clipped, interpolated and handed to the fragment shader
+pub trait VertexShader<Input> {
+    type Varyings: Varyings;
+
+    // the returned position has to be in clip space
+    fn shade_vertex(&self, vertex: &Input) -> ClipVertex<Self::Varyings>;
+}
+
+pub trait FragmentShader<V> {
+    // returning None discards the fragment, neither color nor depth get written
+    fn shade_fragment(&self, fragment: &Fragment<V>) -> Option<Vec4>;
+}
+
+// so plain closures can be used as shaders too
+impl<Input, V, F> VertexShader<Input> for F
+where
+    V: Varyings,
+    F: Fn(&Input) -> ClipVertex<V>,
+{
+    type Varyings = V;
+
+    fn shade_vertex(&self, vertex: &Input) -> ClipVertex<V> {
+        self(vertex)
+    }
+}
+
+impl<V, F> FragmentShader<V> for F
+where
+    F: Fn(&Fragment<V>) -> Option<Vec4>,
+{
+    fn shade_fragment(&self, fragment: &Fragment<V>) -> Option<Vec4> {
+        self(fragment)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DefaultVaryings {
+    pub normal: Vec3,
+    pub color: Vec3,
+    pub uv: Vec2,
+}
+
+crate::impl_varyings!(DefaultVaryings { normal, color, uv });
+
+// Lambert against a fixed light direction plus an ambient, darkened by the
+// material's occlusion. The ambient is constant unless there is an environment map
+// to take the diffuse light from. Vertex colors get multiplied by the material's
+// base color
+pub struct DefaultShader<'a> {
+    pub mvp: Mat4,
+    pub normal_matrix: Mat4,
+    pub material: &'a Material,
+    pub light_dir: Vec3,
+    pub ambient: Vec3,
+    pub environment: Option<&'a EnvironmentMap>,
+}
+
+impl<'a> DefaultShader<'a> {
+    pub fn new(model: &Mat4, mvp: &Mat4, material: &'a Material) -> Self {
+        Self {
+            mvp: *mvp,
+            normal_matrix: cofactor(model),
+            material,
+            light_dir: Vec3::ONE.normalize(),
+            ambient: glam::vec3(0.2, 0.2, 0.2),
+            environment: None,
+        }
+    }
+}
+
+impl<'a> VertexShader<Vertex> for DefaultShader<'a> {
+    type Varyings = DefaultVaryings;
+
+    fn shade_vertex(&self, vertex: &Vertex) -> ClipVertex<DefaultVaryings> {
+        ClipVertex::new(
+            self.mvp * vertex.position.xyz().extend(1.0),
+            DefaultVaryings {
+                normal: (self.normal_matrix * vertex.normal.extend(0.0)).xyz(),
+                color: vertex.color,
+                uv: vertex.uv,
+            },
+        )
+    }
+}
+
+impl<'a> FragmentShader<DefaultVaryings> for DefaultShader<'a> {
+    fn shade_fragment(&self, fragment: &Fragment<DefaultVaryings>) -> Option<Vec4> {
+        let n_dot_l = fragment.varyings.normal.dot(self.light_dir).max(0.0);
+        let (uv, ddx, ddy) = (fragment.varyings.uv, fragment.ddx.uv, fragment.ddy.uv);
+        let base_color = self.material.base_color(uv, ddx, ddy);
+        let albedo = fragment.varyings.color * base_color.xyz();
+        let ambient = match self.environment {
+            Some(environment) => {
+                let normal = fragment.varyings.normal;
+                environment.irradiance.irradiance(normal) / std::f32::consts::PI
+            }
+            None => self.ambient,
+        } * self.material.occlusion(uv, ddx, ddy);
+        let color = albedo * n_dot_l + ambient + self.material.emissive(uv, ddx, ddy);
+        Some(color.extend(base_color.w))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PbrVaryings {
+    // world space
+    pub position: Vec3,
+    pub normal: Vec3,
+    // see Vertex::tangent
+    pub tangent: Vec4,
+    pub color: Vec3,
+    pub uv: Vec2,
+}
+
+crate::impl_varyings!(PbrVaryings {
+    position,
+    normal,
+    tangent,
+    color,
+    uv
+});
+
+// The glTF metallic-roughness material lit by a list of lights (see brdf), plus
+// the environment map, or a constant ambient without one, darkened by the
+// material's occlusion. The material's normal map needs the mesh to have tangents,
+// it is ignored otherwise. Lights with a shadow map at the same index in
+// `shadow_maps` get shadowed by it
+pub struct PbrShader<'a> {
+    pub model: Mat4,
+    pub view_proj: Mat4,
+    pub normal_matrix: Mat4,
+    pub camera_position: Vec3,
+    pub material: &'a Material,
+    pub lights: &'a [Light],
+    pub shadow_maps: &'a [Option<ShadowMap>],
+    pub environment: Option<&'a EnvironmentMap>,
+    pub ambient: Vec3,
+}
+
+impl<'a> PbrShader<'a> {
+    pub fn new(
+        model: &Mat4,
+        view_proj: &Mat4,
+        camera_position: Vec3,
+        material: &'a Material,
+        lights: &'a [Light],
+    ) -> Self {
+        Self {
+            model: *model,
+            view_proj: *view_proj,
+            normal_matrix: cofactor(model),
+            camera_position,
+            material,
+            lights,
+            shadow_maps: &[],
+            environment: None,
+            ambient: glam::vec3(0.03, 0.03, 0.03),
+        }
+    }
+}
+
+impl<'a> VertexShader<Vertex> for PbrShader<'a> {
+    type Varyings = PbrVaryings;
+
+    fn shade_vertex(&self, vertex: &Vertex) -> ClipVertex<PbrVaryings> {
+        let position = self.model * vertex.position.xyz().extend(1.0);
+        ClipVertex::new(
+            self.view_proj * position,
+            PbrVaryings {
+                position: position.xyz(),
+                normal: (self.normal_matrix * vertex.normal.extend(0.0)).xyz(),
+                // tangents lie on the surface, they transform like positions
+                tangent: (self.model * vertex.tangent.xyz().extend(0.0))
+                    .xyz()
+                    .extend(vertex.tangent.w),
+                color: vertex.color,
+                uv: vertex.uv,
+            },
+        )
+    }
+}
+
+// Bends the interpolated normal by a tangent space one from a normal map
+pub fn perturb_normal(normal: Vec3, tangent: Vec4, tangent_space_normal: Vec3) -> Vec3 {
+    let n = normal.normalize_or_zero();
+    // interpolation may have tilted it off the surface
+    let t = (tangent.xyz() - n * n.dot(tangent.xyz())).normalize_or_zero();
+    if t == Vec3::ZERO {
+        return n;
+    }
+    // tangent.w is an interpolated +-1, only its sign means something
+    let b = n.cross(t) * tangent.w.signum();
+    (t * tangent_space_normal.x + b * tangent_space_normal.y + n * tangent_space_normal.z)
+        .normalize_or_zero()
+}
+
+impl<'a> FragmentShader<PbrVaryings> for PbrShader<'a> {
+    fn shade_fragment(&self, fragment: &Fragment<PbrVaryings>) -> Option<Vec4> {
+        let varyings = &fragment.varyings;
+        let (uv, ddx, ddy) = (varyings.uv, fragment.ddx.uv, fragment.ddy.uv);
+        let base_color = self.material.base_color(uv, ddx, ddy);
+        let (metallic, roughness) = self.material.metallic_roughness(uv, ddx, ddy);
+        let surface = SurfaceParams {
+            base_color: varyings.color * base_color.xyz(),
+            metallic,
+            roughness,
+        };
+
+        let n = perturb_normal(
+            varyings.normal,
+            varyings.tangent,
+            self.material.normal(uv, ddx, ddy),
+        );
+        let v = (self.camera_position - varyings.position).normalize_or_zero();
+        let mut direct = Vec3::ZERO;
+        for (i, light) in self.lights.iter().enumerate() {
+            let (l, mut radiance) = light.incident(varyings.position);
+            if let Some(Some(shadow_map)) = self.shadow_maps.get(i) {
+                radiance *= shadow_map.visibility(varyings.position);
+            }
+            direct += surface.shade(n, v, l, radiance);
+        }
+        let ambient = match self.environment {
+            Some(environment) => environment.shade(&surface, n, v),
+            // rough stand-in for light coming from everywhere: diffuse plus the
+            // reflection straight on
+            None => self.ambient * (surface.diffuse_color() + surface.f0()),
+        } * self.material.occlusion(uv, ddx, ddy);
+        let color = direct + ambient + self.material.emissive(uv, ddx, ddy);
+        Some(color.extend(base_color.w))
+    }
+}
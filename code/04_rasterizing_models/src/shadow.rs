@@ -0,0 +1,201 @@
+use crate::{
+    geometry::{Mesh, Vertex},
+    light::Light,
+    model::Model,
+    raster_mesh,
+    render_settings::RenderSettings,
+    render_target::RenderTarget,
+    shader::{Fragment, FragmentShader},
+    utils::coords_to_index,
+    varyings::ClipVertex,
+};
+use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowSettings {
+    // width and height of the shadow map
+    pub resolution: usize,
+    // Pulled off the depth of the surface being shaded before comparing, in shadow map
+    // depth units. Too little and surfaces shadow themselves in stripes (shadow acne),
+    // too much and shadows come off the objects casting them (peter panning)
+    // That's ndc depth, for spot lights it isn't linear: the same bias covers much
+    // less distance close to the light than far from it
+    pub depth_bias: f32,
+    // percentage closer filtering: (2 * radius + 1)^2 texels get compared and
+    // averaged, softening the edges. 0 gives hard, blocky shadows
+    pub pcf_radius: usize,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 1024,
+            depth_bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+}
+
+// The scene's depth as seen from a light: whatever is farther away than what the light
+// sees at the same spot is in shadow
+pub struct ShadowMap {
+    // world space to the light's clip space
+    pub view_proj: Mat4,
+    pub settings: ShadowSettings,
+    // depth only, see RenderTarget::depth_only
+    pub target: RenderTarget,
+}
+
+impl ShadowMap {
+    pub fn new(view_proj: Mat4, settings: ShadowSettings) -> Self {
+        Self {
+            view_proj,
+            settings,
+            target: RenderTarget::depth_only(settings.resolution, settings.resolution),
+        }
+    }
+
+    // Frustum covering the sphere at `center` with `radius` (e.g. bounding the
+    // whole scene) as seen from the light. Point lights would need six maps, one
+    // per cube face, they get None, and so do spot lights whose range ends before
+    // the sphere
+    pub fn for_light(
+        light: &Light,
+        center: Vec3,
+        radius: f32,
+        settings: ShadowSettings,
+    ) -> Option<Self> {
+        match *light {
+            // orthographic, from outside the sphere looking through it
+            Light::Directional { direction, .. } => {
+                let direction = direction.normalize_or_zero();
+                let eye = center - direction * radius * 2.0;
+                let view = Mat4::look_at_rh(eye, center, up_for(direction));
+                let proj =
+                    Mat4::orthographic_rh(-radius, radius, -radius, radius, radius, radius * 3.0);
+                Some(Self::new(proj * view, settings))
+            }
+            // perspective, the same cone as the light
+            Light::Spot {
+                position,
+                direction,
+                range,
+                outer_cone_angle,
+                ..
+            } => {
+                let direction = direction.normalize_or_zero();
+                let view = Mat4::look_at_rh(position, position + direction, up_for(direction));
+                // near as far away as possible, depth precision depends on it
+                let distance = (center - position).length();
+                if range.is_some_and(|range| range <= distance - radius) {
+                    return None;
+                }
+                let far = range.unwrap_or(distance + radius);
+                let near = (distance - radius).max(far * 0.01);
+                let fov = (outer_cone_angle * 2.0).min(std::f32::consts::PI * 0.95);
+                let proj = Mat4::perspective_rh(fov, 1.0, near, far);
+                Some(Self::new(proj * view, settings))
+            }
+            Light::Point { .. } => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.target.clear(Vec4::ZERO);
+    }
+
+    // Depth only pass for an opaque mesh, `model` places it in the world
+    pub fn render_mesh(&mut self, mesh: &Mesh, model: &Mat4) {
+        // never runs, there is no alpha cutoff
+        let fragment_shader = |_: &Fragment<Vec2>| Some(Vec4::ONE);
+        self.render(mesh, model, &fragment_shader, None);
+    }
+
+    // Same for a glTF model, `parent` goes on top of the model's own transform.
+    // Alpha masked materials leave holes in the shadow where they get cut out
+    pub fn render_model(&mut self, model: &Model, parent: &Mat4) {
+        let material = &model.material;
+        let fragment_shader = |fragment: &Fragment<Vec2>| {
+            Some(material.base_color(fragment.varyings, fragment.ddx, fragment.ddy))
+        };
+        let alpha_cutoff = model
+            .render_settings(&RenderSettings::default())
+            .alpha_cutoff;
+        self.render(
+            &model.mesh,
+            &(*parent * model.transform),
+            &fragment_shader,
+            alpha_cutoff,
+        );
+    }
+
+    fn render<F: FragmentShader<Vec2>>(
+        &mut self,
+        mesh: &Mesh,
+        model: &Mat4,
+        fragment_shader: &F,
+        alpha_cutoff: Option<f32>,
+    ) {
+        let mvp = self.view_proj * *model;
+        let vertex_shader =
+            |vertex: &Vertex| ClipVertex::new(mvp * vertex.position.xyz().extend(1.0), vertex.uv);
+        let settings = RenderSettings {
+            depth_only: true,
+            alpha_cutoff,
+            ..Default::default()
+        };
+        raster_mesh(
+            mesh,
+            &vertex_shader,
+            fragment_shader,
+            &settings,
+            &mut self.target,
+        );
+    }
+
+    // How much of the light reaches a world space position: 1 fully lit, 0 fully
+    // in shadow. Anything outside the map is lit
+    pub fn visibility(&self, position: Vec3) -> f32 {
+        let clip = self.view_proj * position.extend(1.0);
+        if clip.w <= 0.0 {
+            return 1.0;
+        }
+        let ndc = clip.xyz() / clip.w;
+        if ndc.z > 1.0 {
+            return 1.0;
+        }
+        let size = self.settings.resolution as f32;
+        // same mapping as the rasterizer, y goes down on screen
+        let x = ((ndc.x * 0.5 + 0.5) * size).floor() as i64;
+        let y = ((-ndc.y * 0.5 + 0.5) * size).floor() as i64;
+        let depth = ndc.z - self.settings.depth_bias;
+
+        let radius = self.settings.pcf_radius as i64;
+        let last = self.settings.resolution as i64 - 1;
+        let mut lit = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (sx, sy) = (x + dx, y + dy);
+                if sx < 0 || sy < 0 || sx > last || sy > last {
+                    lit += 1;
+                    continue;
+                }
+                let id = coords_to_index(sx as usize, sy as usize, self.settings.resolution);
+                if depth <= self.target.depth[id] {
+                    lit += 1;
+                }
+            }
+        }
+        let samples = (2 * radius + 1) * (2 * radius + 1);
+        lit as f32 / samples as f32
+    }
+}
+
+// any up vector works as long as it isn't parallel to the direction
+fn up_for(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
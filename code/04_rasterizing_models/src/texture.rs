@@ -1,84 +1,347 @@
 use crate::utils::*;
 use glam::Vec4;
 use stb_image;
+use std::fmt;
 use std::path::Path;
 
+#[derive(Debug)]
+pub enum TextureError {
+    // the file couldn't be read
+    Io(std::io::Error),
+    // the file was read, but isn't an image stb_image understands
+    Decode(String),
+    // Decoded fine, but we can't store it: more than 4 channels per pixel. Neither
+    // stb_image nor gltf::import hands us such images today, this is for the day one
+    // does, instead of a panic in expand_to_rgba
+    Unsupported(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(error) => write!(f, "can't read texture: {}", error),
+            TextureError::Decode(message) => write!(f, "can't decode texture: {}", message),
+            TextureError::Unsupported(what) => write!(f, "unsupported texture: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(error: std::io::Error) -> Self {
+        TextureError::Io(error)
+    }
+}
+
+// What 8 bit texels hold: colors (e.g. base color) are sRGB encoded, data (e.g. normals
+// or roughness) is stored as is. Either way sampling returns linear values
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Texels {
+    // packed argb
+    Argb8(Vec<u32>),
+    // linear rgba, can go over 1, e.g. HDR environment maps
+    Float(Vec<Vec4>),
+}
+
+impl Texels {
+    // linear rgba, the color space only matters for 8 bit texels
+    pub fn get(&self, id: usize, color_space: ColorSpace) -> Vec4 {
+        match (self, color_space) {
+            (Texels::Argb8(data), ColorSpace::Srgb) => decode_srgb(data[id]),
+            (Texels::Argb8(data), ColorSpace::Linear) => argb8_to_rgba(data[id]),
+            (Texels::Float(data), _) => data[id],
+        }
+    }
+}
+
 pub struct Texture {
     pub width: usize,
     pub height: usize,
-    pub data: Vec<u32>,
+    pub data: Texels,
     pub depth: usize,
+    pub color_space: ColorSpace,
+    // every level half the size of the previous one down to 1x1, starting from half
+    // the size of `data`, see Sampler for how they get used
+    pub mips: Vec<MipLevel>,
+}
+
+pub struct MipLevel {
+    pub width: usize,
+    pub height: usize,
+    pub data: Texels,
+}
+
+impl MipLevel {
+    // Every texel is the average of the 2x2 texels it covers in the level above,
+    // odd sizes repeat the last row or column. Averaged in linear space, sRGB
+    // averaged as is would get darker with every level
+    pub fn downsample(&self, color_space: ColorSpace) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut averages = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec4::ZERO;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(self.width - 1);
+                    let sy = (y * 2 + dy).min(self.height - 1);
+                    sum += self
+                        .data
+                        .get(coords_to_index(sx, sy, self.width), color_space);
+                }
+                averages.push(sum * 0.25);
+            }
+        }
+        let data = match (&self.data, color_space) {
+            (Texels::Argb8(_), ColorSpace::Srgb) => {
+                Texels::Argb8(averages.into_iter().map(encode_srgb).collect())
+            }
+            // rounded, or the image gets darker with every level
+            (Texels::Argb8(_), ColorSpace::Linear) => Texels::Argb8(
+                averages
+                    .into_iter()
+                    .map(|color| rgba_to_argb8(color + 0.5 / 255.0))
+                    .collect(),
+            ),
+            (Texels::Float(_), _) => Texels::Float(averages),
+        };
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
 }
 
 impl Texture {
-    pub fn load(path: &Path) -> Self {
-        let decoded_image = stb_image::image::load(path);
-        if let stb_image::image::LoadResult::ImageU8(image) = decoded_image {
-            let data;
-            if image.depth == 4 {
-                data = (0..image.data.len() / 4)
-                    .map(|id| {
-                        to_argb8(
-                            image.data[id * 4 + 3],
-                            image.data[id * 4],
-                            image.data[id * 4 + 1],
-                            image.data[id * 4 + 2],
-                        )
+    // takes sRGB argb pixels, the mip chain gets built here
+    pub fn new(width: usize, height: usize, data: Vec<u32>, depth: usize) -> Self {
+        Self::from_texels(width, height, Texels::Argb8(data), depth, ColorSpace::Srgb)
+    }
+
+    // linear rgba pixels
+    pub fn new_float(width: usize, height: usize, data: Vec<Vec4>, depth: usize) -> Self {
+        Self::from_texels(
+            width,
+            height,
+            Texels::Float(data),
+            depth,
+            ColorSpace::Linear,
+        )
+    }
+
+    pub fn from_texels(
+        width: usize,
+        height: usize,
+        data: Texels,
+        depth: usize,
+        color_space: ColorSpace,
+    ) -> Self {
+        let base = MipLevel {
+            width,
+            height,
+            data,
+        };
+        let mut mips: Vec<MipLevel> = Vec::new();
+        loop {
+            let last = mips.last().unwrap_or(&base);
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample(color_space);
+            mips.push(next);
+        }
+        Self {
+            width,
+            height,
+            data: base.data,
+            depth,
+            color_space,
+            mips,
+        }
+    }
+
+    // number of levels, including the full size one
+    pub fn level_count(&self) -> usize {
+        self.mips.len() + 1
+    }
+
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        match level {
+            0 => (self.width, self.height),
+            _ => (self.mips[level - 1].width, self.mips[level - 1].height),
+        }
+    }
+
+    // linear rgba of a texel, x and y have to be inside the level
+    pub fn texel(&self, level: usize, x: usize, y: usize) -> Vec4 {
+        let (width, data) = match level {
+            0 => (self.width, &self.data),
+            _ => (self.mips[level - 1].width, &self.mips[level - 1].data),
+        };
+        data.get(coords_to_index(x, y, width), self.color_space)
+    }
+
+    // 8 bit images are taken as sRGB, see load_with_color_space
+    pub fn load(path: &Path) -> Result<Self, TextureError> {
+        Self::load_with_color_space(path, ColorSpace::Srgb)
+    }
+
+    // floating point images (.hdr) are always linear
+    pub fn load_with_color_space(
+        path: &Path,
+        color_space: ColorSpace,
+    ) -> Result<Self, TextureError> {
+        // read the file ourselves, stb_image would report a missing file as a decode error
+        let bytes = std::fs::read(path)?;
+        let (width, height, depth, data) = match stb_image::image::load_from_memory(&bytes) {
+            stb_image::image::LoadResult::ImageU8(image) => {
+                check_channels(image.depth)?;
+                let data = image
+                    .data
+                    .chunks_exact(image.depth)
+                    .map(|pixel| {
+                        let [r, g, b, a] = expand_to_rgba(pixel, 255);
+                        to_argb8(a, r, g, b)
                     })
                     .collect();
-            } else {
-                data = (0..image.data.len() / 3)
-                    .map(|id| {
-                        to_argb8(
-                            255,
-                            image.data[id * 3],
-                            image.data[id * 3 + 1],
-                            image.data[id * 3 + 2],
-                        )
-                    })
+                (image.width, image.height, image.depth, Texels::Argb8(data))
+            }
+            stb_image::image::LoadResult::ImageF32(image) => {
+                check_channels(image.depth)?;
+                let data = image
+                    .data
+                    .chunks_exact(image.depth)
+                    .map(|pixel| Vec4::from(expand_to_rgba(pixel, 1.0)))
                     .collect();
+                (image.width, image.height, image.depth, Texels::Float(data))
             }
-            Self {
-                width: image.width,
-                height: image.height,
-                data,
-                depth: image.depth,
+            stb_image::image::LoadResult::Error(message) => {
+                return Err(TextureError::Decode(message))
             }
-        } else {
-            panic!("Unsupported texture type");
+        };
+        Ok(Self::from_texels(width, height, data, depth, color_space))
+    }
+
+    // Images decoded by gltf::import. 16 bit channels are cut down to 8 bits
+    pub fn from_gltf_image(
+        image: &gltf::image::Data,
+        color_space: ColorSpace,
+    ) -> Result<Self, TextureError> {
+        use gltf::image::Format;
+        let (width, height) = (image.width as usize, image.height as usize);
+        let (depth, bytes) = match image.format {
+            Format::R8 => (1, 1),
+            Format::R8G8 => (2, 1),
+            Format::R8G8B8 => (3, 1),
+            Format::R8G8B8A8 => (4, 1),
+            Format::R16 => (1, 2),
+            Format::R16G16 => (2, 2),
+            Format::R16G16B16 => (3, 2),
+            Format::R16G16B16A16 => (4, 2),
+            Format::R32G32B32FLOAT => (3, 4),
+            Format::R32G32B32A32FLOAT => (4, 4),
+        };
+        if image.pixels.len() != width * height * depth * bytes {
+            return Err(TextureError::Decode(format!(
+                "{} bytes for a {}x{} {:?} image",
+                image.pixels.len(),
+                width,
+                height,
+                image.format
+            )));
         }
+
+        let pixels = image.pixels.chunks_exact(depth * bytes);
+        let data = match bytes {
+            1 => Texels::Argb8(
+                pixels
+                    .map(|pixel| {
+                        let [r, g, b, a] = expand_to_rgba(pixel, 255);
+                        to_argb8(a, r, g, b)
+                    })
+                    .collect(),
+            ),
+            2 => Texels::Argb8(
+                pixels
+                    .map(|pixel| {
+                        // the most significant byte of every channel
+                        let channels: Vec<u8> = pixel
+                            .chunks_exact(2)
+                            .map(|c| (u16::from_ne_bytes([c[0], c[1]]) >> 8) as u8)
+                            .collect();
+                        let [r, g, b, a] = expand_to_rgba(&channels, 255);
+                        to_argb8(a, r, g, b)
+                    })
+                    .collect(),
+            ),
+            _ => Texels::Float(
+                pixels
+                    .map(|pixel| {
+                        let channels: Vec<f32> = pixel
+                            .chunks_exact(4)
+                            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                            .collect();
+                        Vec4::from(expand_to_rgba(&channels, 1.0))
+                    })
+                    .collect(),
+            ),
+        };
+        Ok(Self::from_texels(width, height, data, depth, color_space))
     }
 
+    // nearest texel, repeating. See Sampler for filtering and other address modes
     pub fn uv_to_index(&self, u: f32, v: f32) -> usize {
         let (u, v) = (u * self.width as f32, v * self.height as f32);
         coords_to_index(
-            (u as usize) % self.width,
-            (v as usize) % self.height,
+            (u.floor() as i64).rem_euclid(self.width as i64) as usize,
+            (v.floor() as i64).rem_euclid(self.height as i64) as usize,
             self.width,
         )
     }
 
+    // the stored texel, float ones get encoded to sRGB
     pub fn argb_at_uv(&self, u: f32, v: f32) -> u32 {
         let id = self.uv_to_index(u, v);
-        if id < self.data.len() {
-            self.data[id]
-        } else {
-            to_argb8(255, 255, 0, 255)
+        match &self.data {
+            Texels::Argb8(data) => data[id],
+            Texels::Float(data) => encode_srgb(data[id]),
         }
     }
+}
 
-    pub fn argb_at_uvf(&self, u: f32, v: f32) -> Vec4 {
-        let id = self.uv_to_index(u, v);
-        if id < self.data.len() {
-            let color = from_argb8(self.data[id]);
-            Vec4::new(
-                (color.0 as f32) / 255.0,
-                (color.1 as f32) / 255.0,
-                (color.2 as f32) / 255.0,
-                (color.3 as f32) / 255.0,
-            )
-        } else {
-            Vec4::new(1.0, 1.0, 0.0, 1.0)
-        }
+fn check_channels(depth: usize) -> Result<(), TextureError> {
+    match depth {
+        1..=4 => Ok(()),
+        _ => Err(TextureError::Unsupported(format!(
+            "{} channels per pixel",
+            depth
+        ))),
+    }
+}
+
+// gray, gray and alpha, rgb or rgba, `opaque` is the alpha of the ones without
+fn expand_to_rgba<T: Copy>(pixel: &[T], opaque: T) -> [T; 4] {
+    match *pixel {
+        [l] => [l, l, l, opaque],
+        [l, a] => [l, l, l, a],
+        [r, g, b] => [r, g, b, opaque],
+        [r, g, b, a] => [r, g, b, a],
+        _ => unreachable!("{} channels", pixel.len()),
     }
 }
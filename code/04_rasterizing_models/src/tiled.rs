@@ -0,0 +1,137 @@
+use crate::{
+    geometry::{Mesh, Triangle},
+    raster_mesh, raster_triangle_setup,
+    render_settings::RenderSettings,
+    render_target::RenderTarget,
+    setup_clip_space_triangle,
+    shader::{FragmentShader, VertexShader},
+    utils::index_to_coords,
+    varyings::ClipVertex,
+};
+use std::sync::Mutex;
+
+// even, so the 2x2 pixel quads never straddle two tiles
+pub const TILE_SIZE: usize = 64;
+
+// Same output as raster_mesh, but split over worker threads:
+// every triangle is set up once, binned into the screen tiles it touches,
+// then each tile is rasterized by a single worker on its own copy of the pixels,
+// going through its triangles in submission order.
+// One worker per core, with a single core it's just raster_mesh
+pub fn raster_mesh_tiled<In, VS, F>(
+    mesh: &Mesh<In>,
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+) where
+    In: Copy,
+    VS: VertexShader<In>,
+    VS::Varyings: Send + Sync,
+    F: FragmentShader<VS::Varyings> + Sync,
+{
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    raster_mesh_tiled_with_workers(
+        mesh,
+        vertex_shader,
+        fragment_shader,
+        settings,
+        target,
+        workers,
+    );
+}
+
+// Copying the tiles around and spawning the threads only pays off with more than
+// one worker, otherwise this falls back to raster_mesh
+pub fn raster_mesh_tiled_with_workers<In, VS, F>(
+    mesh: &Mesh<In>,
+    vertex_shader: &VS,
+    fragment_shader: &F,
+    settings: &RenderSettings,
+    target: &mut RenderTarget,
+    workers: usize,
+) where
+    In: Copy,
+    VS: VertexShader<In>,
+    VS::Varyings: Send + Sync,
+    F: FragmentShader<VS::Varyings> + Sync,
+{
+    if workers <= 1 {
+        raster_mesh(mesh, vertex_shader, fragment_shader, settings, target);
+        return;
+    }
+    let viewport_size = target.size();
+    let shaded: Vec<ClipVertex<VS::Varyings>> = mesh
+        .vertices()
+        .iter()
+        .map(|vertex| vertex_shader.shade_vertex(vertex))
+        .collect();
+
+    let mut setups = Vec::new();
+    for triangle in mesh.triangles() {
+        let clip_tri = Triangle {
+            v0: shaded[triangle.x as usize],
+            v1: shaded[triangle.y as usize],
+            v2: shaded[triangle.z as usize],
+        };
+        setup_clip_space_triangle(&clip_tri, settings, viewport_size, |setup| {
+            setups.push(setup)
+        });
+    }
+
+    let tiles_x = target.width.div_ceil(TILE_SIZE);
+    let tiles_y = target.height.div_ceil(TILE_SIZE);
+    let mut bins: Vec<Vec<usize>> = vec![Vec::new(); tiles_x * tiles_y];
+    for (id, setup) in setups.iter().enumerate() {
+        let bb = &setup.bounding_box;
+        for tile_y in (bb.top as usize / TILE_SIZE)..=(bb.bottom as usize / TILE_SIZE) {
+            for tile_x in (bb.left as usize / TILE_SIZE)..=(bb.right as usize / TILE_SIZE) {
+                bins[tile_x + tile_y * tiles_x].push(id);
+            }
+        }
+    }
+
+    // only tiles with something to draw, the rest of the target stays untouched
+    let tiles: Vec<_> = bins
+        .iter()
+        .enumerate()
+        .filter(|(_, bin)| !bin.is_empty())
+        .map(|(id, _)| {
+            let (tile_x, tile_y) = index_to_coords(id, tiles_x);
+            let (left, top) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+            let width = TILE_SIZE.min(target.width - left);
+            let height = TILE_SIZE.min(target.height - top);
+            (id, target.read_tile(left, top, width, height))
+        })
+        .collect();
+
+    let queue = Mutex::new(tiles.into_iter());
+    let done = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                // the guard is dropped at the end of the statement, so workers don't serialize
+                let next = queue.lock().unwrap().next();
+                match next {
+                    Some((id, mut tile)) => {
+                        let mut view = tile.view();
+                        for setup_id in &bins[id] {
+                            raster_triangle_setup(
+                                &setups[*setup_id],
+                                fragment_shader,
+                                settings,
+                                &mut view,
+                            );
+                        }
+                        done.lock().unwrap().push(tile);
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+
+    for tile in done.into_inner().unwrap() {
+        target.write_tile(&tile);
+    }
+}
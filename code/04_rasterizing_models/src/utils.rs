@@ -1,9 +1,81 @@
-use glam::{Mat4, Vec2, Vec3};
+use glam::{IVec2, Mat4, Vec2, Vec3, Vec4};
 //clockwise
 pub fn edge_function(v0: Vec2, v1: Vec2, p: Vec2) -> f32 {
     (p.x - v0.x) * (v1.y - v0.y) - (p.y - v0.y) * (v1.x - v0.x)
 }
 
+// Screen positions are snapped to a grid of 1/SUBPIXEL_STEPS of a pixel before
+// rasterizing, so edge functions can be evaluated exactly with integers
+pub const SUBPIXEL_BITS: u32 = 8;
+pub const SUBPIXEL_STEPS: i32 = 1 << SUBPIXEL_BITS;
+
+pub fn snap_to_subpixel(p: Vec2) -> IVec2 {
+    (p * SUBPIXEL_STEPS as f32).round().as_ivec2()
+}
+
+pub fn from_subpixel(p: IVec2) -> Vec2 {
+    p.as_vec2() / SUBPIXEL_STEPS as f32
+}
+
+// center of pixel (x, y) on the subpixel grid
+pub fn subpixel_center(x: usize, y: usize) -> IVec2 {
+    glam::ivec2(x as i32, y as i32) * SUBPIXEL_STEPS + SUBPIXEL_STEPS / 2
+}
+
+// edge_function(v0, v1, p) on the subpixel grid, as a linear function of p:
+// a * p.x + b * p.y + c. Integers make it exact, so two triangles sharing an edge
+// always agree on which side of it a pixel is
+#[derive(Debug, Copy, Clone)]
+pub struct EdgeEquation {
+    pub a: i64,
+    pub b: i64,
+    pub c: i64,
+}
+
+impl EdgeEquation {
+    pub fn new(v0: IVec2, v1: IVec2) -> Self {
+        let a = (v1.y - v0.y) as i64;
+        let b = -(v1.x - v0.x) as i64;
+        Self {
+            a,
+            b,
+            c: -(a * v0.x as i64 + b * v0.y as i64),
+        }
+    }
+
+    pub fn evaluate(&self, p: IVec2) -> i64 {
+        self.a * p.x as i64 + self.b * p.y as i64 + self.c
+    }
+
+    // how much the value changes moving one pixel to the right
+    pub fn step_x(&self) -> i64 {
+        self.a << SUBPIXEL_BITS
+    }
+
+    // swaps inside and outside
+    pub fn flip(&mut self) {
+        self.a = -self.a;
+        self.b = -self.b;
+        self.c = -self.c;
+    }
+
+    // With the inside positive, a left edge has the inside to its right (x grows inside)
+    // and a top edge is horizontal with the inside below it (y grows downwards)
+    pub fn is_top_left(&self) -> bool {
+        self.a > 0 || (self.a == 0 && self.b > 0)
+    }
+
+    // Top-left fill rule: a pixel center exactly on an edge belongs to the triangle only
+    // when that is a top or left edge, so pixels on shared edges get drawn once
+    pub fn min_inside(&self) -> i64 {
+        if self.is_top_left() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
 pub fn barycentric_coordinates(
     point: Vec2,
     v0: Vec2,
@@ -50,6 +122,69 @@ pub fn from_argb8(argb: u32) -> (u8, u8, u8, u8) {
     (a, r, g, b)
 }
 
+// shaders work with rgba in [0, 1], the frame buffer stores packed argb
+pub fn rgba_to_argb8(color: Vec4) -> u32 {
+    to_argb8(
+        (color.w * 255.0) as u8,
+        (color.x * 255.0) as u8,
+        (color.y * 255.0) as u8,
+        (color.z * 255.0) as u8,
+    )
+}
+
+pub fn argb8_to_rgba(argb: u32) -> Vec4 {
+    let (a, r, g, b) = from_argb8(argb);
+    glam::vec4(r as f32, g as f32, b as f32, a as f32) / 255.0
+}
+
+// Textures and the screen store sRGB encoded colors: more of the 256 steps are spent on
+// dark values, where the eye is more sensitive. Light adds up linearly though, so
+// shading and blending happen on linear values, decoded on read and encoded on present
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// same as srgb_to_linear(value / 255), from a table since textures do this all the time
+pub fn srgb8_to_linear(value: u8) -> f32 {
+    static TABLE: std::sync::OnceLock<[f32; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)))[value as usize]
+}
+
+// alpha is always linear
+pub fn decode_srgb(argb: u32) -> Vec4 {
+    let (a, r, g, b) = from_argb8(argb);
+    glam::vec4(
+        srgb8_to_linear(r),
+        srgb8_to_linear(g),
+        srgb8_to_linear(b),
+        a as f32 / 255.0,
+    )
+}
+
+// clamped to [0, 1] and rounded to the closest step
+pub fn encode_srgb(color: Vec4) -> u32 {
+    let color = color.clamp(Vec4::ZERO, Vec4::ONE);
+    let encoded = glam::vec4(
+        linear_to_srgb(color.x),
+        linear_to_srgb(color.y),
+        linear_to_srgb(color.z),
+        color.w,
+    );
+    rgba_to_argb8(encoded + 0.5 / 255.0)
+}
+
 pub fn lerp<T>(start: T, end: T, alpha: f32) -> T
 where
     T: std::ops::Sub<Output = T>
@@ -71,7 +206,7 @@ where
     b1 + (v - a1) * (b2 - b1) / (a2 - a1)
 }
 
-pub fn clear_buffer<T>(buffer: &mut Vec<T>, value: T)
+pub fn clear_buffer<T>(buffer: &mut [T], value: T)
 where
     T: Copy,
 {
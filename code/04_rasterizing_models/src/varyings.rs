@@ -0,0 +1,108 @@
+use glam::Vec4;
+use std::ops::{Add, Mul, Sub};
+
+// Anything the vertex shader wants to hand over to the fragment shader.
+// Clipping and interpolation only need to blend values, so these operations are all
+// we ask for: f32 and the glam vectors already qualify, for custom structs see impl_varyings!
+pub trait Varyings:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self>
+{
+}
+
+impl<T> Varyings for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> {}
+
+/// Implements the arithmetic needed by Varyings field by field, every field has to be listed
+///
+/// ```
+/// use glam::{Vec2, Vec3};
+/// use ruster::{impl_varyings, lerp};
+///
+/// #[derive(Debug, Copy, Clone)]
+/// struct MyVaryings {
+///     tangent: Vec3,
+///     uv1: Vec2,
+/// }
+/// impl_varyings!(MyVaryings { tangent, uv1 });
+///
+/// let a = MyVaryings { tangent: Vec3::X, uv1: Vec2::ZERO };
+/// let b = MyVaryings { tangent: Vec3::Y, uv1: Vec2::ONE };
+/// assert_eq!(lerp(a, b, 0.5).uv1, Vec2::splat(0.5));
+/// ```
+#[macro_export]
+macro_rules! impl_varyings {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl ::std::ops::Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self {
+                    $($field: self.$field + rhs.$field,)*
+                }
+            }
+        }
+
+        impl ::std::ops::Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self {
+                    $($field: self.$field - rhs.$field,)*
+                }
+            }
+        }
+
+        impl ::std::ops::Mul<f32> for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: f32) -> Self {
+                Self {
+                    $($field: self.$field * rhs,)*
+                }
+            }
+        }
+
+        impl ::std::ops::MulAssign<f32> for $name {
+            fn mul_assign(&mut self, rhs: f32) {
+                $(self.$field = self.$field * rhs;)*
+            }
+        }
+    };
+}
+
+// Output of the vertex shader: the clip space position the rasterizer needs
+// plus whatever the shader wants interpolated
+#[derive(Debug, Copy, Clone)]
+pub struct ClipVertex<V> {
+    pub position: Vec4,
+    pub varyings: V,
+}
+
+impl<V> ClipVertex<V> {
+    pub fn new(position: Vec4, varyings: V) -> Self {
+        Self { position, varyings }
+    }
+}
+
+impl<V: Varyings> Add for ClipVertex<V> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.position + rhs.position, self.varyings + rhs.varyings)
+    }
+}
+
+impl<V: Varyings> Sub for ClipVertex<V> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.position - rhs.position, self.varyings - rhs.varyings)
+    }
+}
+
+impl<V: Varyings> Mul<f32> for ClipVertex<V> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.position * rhs, self.varyings * rhs)
+    }
+}
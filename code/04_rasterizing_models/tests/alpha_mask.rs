@@ -0,0 +1,98 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3, Vec4};
+use ruster::*;
+use std::sync::Arc;
+
+// left half opaque, right half fully transparent
+fn cutout_texture() -> Texture {
+    let data = vec![to_argb8(255, 255, 255, 255), to_argb8(0, 255, 255, 255)];
+    Texture::new(2, 1, data, 4)
+}
+
+#[test]
+fn masked_pixels_write_neither_color_nor_depth() {
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+
+    let model = Model {
+        mesh: plane(glam::vec2(2.0, 2.0), 1),
+        material: Material {
+            alpha_mode: AlphaMode::Mask { cutoff: 0.5 },
+            ..Material::from_texture(Arc::new(cutout_texture()))
+        },
+        ..Default::default()
+    };
+    let settings = model.render_settings(&RenderSettings::default());
+    let shader = DefaultShader::new(&Mat4::IDENTITY, &view_proj, &model.material);
+    raster_mesh(&model.mesh, &shader, &shader, &settings, &mut target);
+
+    let left = coords_to_index(WIDTH / 2 - 10, HEIGHT / 2, WIDTH);
+    let right = coords_to_index(WIDTH / 2 + 10, HEIGHT / 2, WIDTH);
+    assert_ne!(target.color[left], Vec4::ZERO);
+    assert!(target.depth[left].is_finite());
+    // alpha was 0, so nothing got written
+    assert_eq!(target.color[right], Vec4::ZERO);
+    assert_eq!(target.depth[right], f32::INFINITY);
+
+    // something behind still shows through the hole
+    let behind = Mat4::from_translation(glam::vec3(0.0, 0.0, -1.0));
+    let blue = |_: &Fragment<DefaultVaryings>| Some(Vec3::Z.extend(1.0));
+    let material = Material::default();
+    let shader = DefaultShader::new(&behind, &(view_proj * behind), &material);
+    raster_mesh(
+        &model.mesh,
+        &shader,
+        &blue,
+        &RenderSettings::default(),
+        &mut target,
+    );
+    assert_eq!(target.color[right], Vec4::new(0.0, 0.0, 1.0, 1.0));
+    assert_ne!(target.color[left], Vec4::new(0.0, 0.0, 1.0, 1.0));
+}
+
+#[test]
+fn masked_pixels_are_opaque() {
+    // half transparent, but above the cutoff
+    let fragment_shader = |_: &Fragment<f32>| Some(glam::vec4(1.0, 0.0, 0.0, 0.6));
+    let vertex_shader = |vertex: &Vertex| ClipVertex::new(vertex.position, 0.0);
+    let settings = RenderSettings {
+        alpha_cutoff: Some(0.5),
+        ..Default::default()
+    };
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let quad = plane(glam::Vec2::ONE, 1);
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    let center = target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)];
+    assert_eq!(center, glam::vec4(1.0, 0.0, 0.0, 1.0));
+}
+
+#[test]
+fn gltf_alpha_cutoff() {
+    let path = write_gltf_quad("mask", r#"{ "alphaMode": "MASK", "alphaCutoff": 0.3 }"#, "");
+    let models = load_gltf_models(&path).unwrap();
+    assert_eq!(
+        models[0].material.alpha_mode,
+        AlphaMode::Mask { cutoff: 0.3 }
+    );
+    assert!(!models[0].is_transparent());
+    let settings = models[0].render_settings(&RenderSettings::default());
+    assert_eq!(settings.alpha_cutoff, Some(0.3));
+    assert!(settings.depth.write);
+
+    // the cutoff defaults to 0.5
+    let path = write_gltf_quad("mask_default", r#"{ "alphaMode": "MASK" }"#, "");
+    let models = load_gltf_models(&path).unwrap();
+    assert_eq!(
+        models[0].material.alpha_mode,
+        AlphaMode::Mask { cutoff: 0.5 }
+    );
+}
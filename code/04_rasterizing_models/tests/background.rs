@@ -0,0 +1,145 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use ruster::background::view_direction;
+use ruster::*;
+
+#[test]
+fn directions() {
+    for reversed_z in [false, true] {
+        let mut camera = Camera {
+            reversed_z,
+            ..camera_at(glam::vec3(1.0, 2.0, 3.0))
+        };
+        let inverse = (camera.projection() * camera.view()).inverse();
+        assert_close(view_direction(&inverse, glam::Vec2::ZERO), -Vec3::Z, 1e-4);
+        // fov / 2 up at the top edge, ndc y goes up
+        let top = view_direction(&inverse, glam::vec2(0.0, 1.0));
+        let half_fov = camera.fov / 2.0;
+        assert_close(top, glam::vec3(0.0, half_fov.sin(), -half_fov.cos()), 1e-4);
+        let right = view_direction(&inverse, glam::vec2(1.0, 0.0));
+        assert!(right.x > 0.0 && right.y.abs() < 1e-4, "{:?}", right);
+
+        // looking right, only the rotation matters
+        camera.transform.rotation = glam::Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2);
+        let inverse = (camera.projection() * camera.view()).inverse();
+        assert_close(view_direction(&inverse, glam::Vec2::ZERO), Vec3::X, 1e-4);
+    }
+}
+
+#[test]
+fn fills_only_untouched_pixels() {
+    let sky = glam::vec3(0.2, 0.4, 0.8);
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    let material = Material::default();
+    let shader = DefaultShader::new(&Mat4::IDENTITY, &view_proj, &material);
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    raster_mesh(
+        &cube(),
+        &shader,
+        &shader,
+        &RenderSettings::default(),
+        &mut target,
+    );
+    let before = target.color.clone();
+    Background::Color(sky).draw(&view_proj, f32::INFINITY, &mut target);
+
+    let mut filled = 0;
+    for (id, depth) in target.depth.iter().enumerate() {
+        if depth.is_finite() {
+            assert_eq!(target.color[id], before[id]);
+        } else {
+            assert_eq!(target.color[id], sky.extend(1.0));
+            filled += 1;
+        }
+    }
+    assert!(filled > 0 && filled < WIDTH * HEIGHT);
+}
+
+#[test]
+fn reversed_z() {
+    let camera = Camera {
+        reversed_z: true,
+        ..camera_at(glam::vec3(0.0, 0.0, 3.0))
+    };
+    let view_proj = camera.projection() * camera.view();
+    let material = Material::default();
+    let shader = DefaultShader::new(&Mat4::IDENTITY, &view_proj, &material);
+    let settings = RenderSettings {
+        depth: DepthState::reversed_z(),
+        ..Default::default()
+    };
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    target.clear_with_depth(glam::Vec4::ZERO, 0.0);
+    raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    Background::Color(Vec3::ONE).draw(&view_proj, 0.0, &mut target);
+    // the cube is still there, the corners are background
+    let center = target.color[HEIGHT / 2 * WIDTH + WIDTH / 2];
+    assert!(center != glam::Vec4::ONE);
+    assert_eq!(target.color[0], glam::Vec4::ONE);
+}
+
+// Transparent surfaces don't write depth, so the background has to go before them
+#[test]
+fn transparent_goes_after_background() {
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    let vertex_shader = |vertex: &Vertex| ClipVertex::new(view_proj * vertex.position, 0.0);
+    let fragment_shader = |_: &Fragment<f32>| Some(glam::vec4(1.0, 0.0, 0.0, 0.5));
+    let mut settings = RenderSettings {
+        blend: BlendMode::Alpha,
+        ..Default::default()
+    };
+    settings.depth.write = false;
+    let quad = plane(glam::vec2(1.0, 1.0), 1);
+    let background = Background::Color(Vec3::Z);
+    let center = HEIGHT / 2 * WIDTH + WIDTH / 2;
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    background.draw(&view_proj, f32::INFINITY, &mut target);
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    assert_eq!(target.color[center], glam::vec4(0.5, 0.0, 0.5, 1.0));
+
+    // the other way around the quad gets painted over
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    background.draw(&view_proj, f32::INFINITY, &mut target);
+    assert_eq!(target.color[center], Vec3::Z.extend(1.0));
+}
+
+#[test]
+fn cube_map() {
+    let colors = [
+        0xffff0000, 0xff00ffff, 0xff00ff00, 0xffff00ff, 0xff0000ff, 0xffffff00,
+    ];
+    let cube_map = CubeMap::new(colors.map(|color| Texture::new(1, 1, vec![color], 4)));
+    let axes = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+    for (face, (axis, color)) in axes.iter().zip(colors).enumerate() {
+        assert_eq!(CubeMap::face_uv(*axis), (face, glam::vec2(0.5, 0.5)));
+        assert_eq!(cube_map.sample(*axis), decode_srgb(color));
+        let background = Background::CubeMap(&cube_map);
+        assert_eq!(background.radiance(*axis * 3.0), decode_srgb(color).xyz());
+    }
+    // +X: u goes towards -Z, v down
+    let (face, uv) = CubeMap::face_uv(glam::vec3(1.0, 0.5, -0.5));
+    assert_eq!((face, uv), (0, glam::vec2(0.75, 0.25)));
+    // -Z, seen from inside: u goes towards -X
+    let (face, uv) = CubeMap::face_uv(glam::vec3(0.5, -0.5, -1.0));
+    assert_eq!((face, uv), (5, glam::vec2(0.25, 0.75)));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::*;
+use glam::{Vec3, Vec4};
+use ruster::*;
+
+#[test]
+fn blend_modes() {
+    let dst = glam::vec4(0.0, 0.0, 1.0, 1.0);
+    let red = glam::vec4(1.0, 0.0, 0.0, 0.25);
+    let cases = [
+        (BlendMode::Opaque, red, red),
+        (BlendMode::Alpha, red, glam::vec4(0.25, 0.0, 0.75, 1.0)),
+        (
+            BlendMode::PremultipliedAlpha,
+            glam::vec4(0.25, 0.0, 0.0, 0.25),
+            glam::vec4(0.25, 0.0, 0.75, 1.0),
+        ),
+        (BlendMode::Additive, red, glam::vec4(0.25, 0.0, 1.0, 1.0)),
+        (
+            BlendMode::Multiply,
+            glam::vec4(0.5, 1.0, 0.5, 1.0),
+            glam::vec4(0.0, 0.0, 0.5, 1.0),
+        ),
+    ];
+    for (mode, src, expected) in cases {
+        let result = mode.blend(src, dst);
+        assert!(result.abs_diff_eq(expected, 1e-6), "{:?}: {}", mode, result);
+    }
+}
+
+// unit quad at the given depth, in a single color
+fn quad(z: f32, color: Vec3) -> Mesh {
+    let quad = plane(glam::Vec2::ONE, 1);
+    let vertices: Vec<Vertex> = quad
+        .vertices()
+        .iter()
+        .map(|vertex| {
+            let position = vertex.position + glam::vec4(0.0, 0.0, z, 0.0);
+            Vertex::new(position, vertex.normal, color, vertex.uv)
+        })
+        .collect();
+    Mesh::from_vertices(quad.triangles(), &vertices)
+}
+
+fn render_transparent(sorted: bool) -> Vec4 {
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    // submitted front to back, the worst order for blending
+    let mesh = quad(0.5, Vec3::X) + quad(-0.5, Vec3::Z);
+
+    let vertex_shader =
+        |vertex: &Vertex| ClipVertex::new(view_proj * vertex.position, vertex.color);
+    let fragment_shader = |fragment: &Fragment<Vec3>| Some(fragment.varyings.extend(0.5));
+    let model = Model {
+        mesh,
+        material: Material {
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let settings = model.render_settings(&RenderSettings::default());
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    if sorted {
+        raster_mesh_sorted(
+            &model.mesh,
+            &vertex_shader,
+            &fragment_shader,
+            &settings,
+            &mut target,
+        );
+    } else {
+        raster_mesh(
+            &model.mesh,
+            &vertex_shader,
+            &fragment_shader,
+            &settings,
+            &mut target,
+        );
+    }
+    target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)]
+}
+
+#[test]
+fn transparent_pass_sorts_back_to_front() {
+    // blue behind, then red over it
+    let sorted = render_transparent(true);
+    assert!(
+        sorted.abs_diff_eq(glam::vec4(0.5, 0.0, 0.25, 0.75), 1.0 / 255.0),
+        "{}",
+        sorted
+    );
+
+    // without sorting blue ends up on top, even though it is behind
+    let unsorted = render_transparent(false);
+    assert!(unsorted.z > unsorted.x, "{}", unsorted);
+}
+
+#[test]
+fn gltf_alpha_mode() {
+    let path = write_gltf_quad("blend", r#"{ "alphaMode": "BLEND" }"#, "");
+    let models = load_gltf_models(&path).unwrap();
+    assert_eq!(models.len(), 1);
+    assert_eq!(models[0].mesh.triangles().len(), 2);
+    assert!(models[0].is_transparent());
+    let settings = models[0].render_settings(&RenderSettings::default());
+    assert_eq!(settings.blend, BlendMode::Alpha);
+    assert!(!settings.depth.write);
+
+    let path = write_gltf_quad("opaque", "{}", "");
+    let models = load_gltf_models(&path).unwrap();
+    assert_eq!(models[0].material.alpha_mode, AlphaMode::Opaque);
+    let settings = models[0].render_settings(&RenderSettings::default());
+    assert_eq!(settings.blend, BlendMode::Opaque);
+    assert!(settings.depth.write);
+}
+
+#[test]
+fn model_center() {
+    let model = Model {
+        mesh: quad(-2.0, Vec3::ONE),
+        ..Default::default()
+    };
+    assert_eq!(model.center(), glam::vec3(0.0, 0.0, -2.0));
+    assert_eq!(Model::default().center(), Vec3::ZERO);
+}
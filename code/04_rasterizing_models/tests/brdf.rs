@@ -0,0 +1,111 @@
+use glam::Vec3;
+use ruster::brdf::*;
+use std::f32::consts::PI;
+
+// midpoint rule over the hemisphere around +Z
+fn integrate_hemisphere<F: Fn(Vec3) -> f32>(f: F) -> f32 {
+    let (n_theta, n_phi) = (256, 64);
+    let (d_theta, d_phi) = (0.5 * PI / n_theta as f32, 2.0 * PI / n_phi as f32);
+    let mut sum = 0.0;
+    for i in 0..n_theta {
+        let theta = (i as f32 + 0.5) * d_theta;
+        for j in 0..n_phi {
+            let phi = (j as f32 + 0.5) * d_phi;
+            let dir = glam::vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            sum += f(dir) * theta.sin() * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+#[test]
+fn ggx_is_normalized() {
+    // the projected area of the microfacets adds up to the macro surface
+    for alpha in [0.1f32, 0.3, 0.6, 1.0] {
+        let area = integrate_hemisphere(|h| distribution_ggx(h.z, alpha) * h.z);
+        assert!((area - 1.0).abs() < 0.01, "alpha {}: {}", alpha, area);
+    }
+}
+
+#[test]
+fn fresnel() {
+    let f0 = Vec3::splat(DIELECTRIC_F0);
+    assert_eq!(fresnel_schlick(f0, 1.0), f0);
+    assert_eq!(fresnel_schlick(f0, 0.0), Vec3::ONE);
+}
+
+#[test]
+fn metallic_roughness() {
+    let color = glam::vec3(0.9, 0.4, 0.1);
+    let dielectric = SurfaceParams {
+        base_color: color,
+        metallic: 0.0,
+        roughness: 0.5,
+    };
+    assert_eq!(dielectric.diffuse_color(), color);
+    assert_eq!(dielectric.f0(), Vec3::splat(DIELECTRIC_F0));
+    assert_eq!(dielectric.alpha(), 0.25);
+
+    let metal = SurfaceParams {
+        metallic: 1.0,
+        ..dielectric
+    };
+    assert_eq!(metal.diffuse_color(), Vec3::ZERO);
+    assert_eq!(metal.f0(), color);
+
+    // away from the highlight a metal is black, a dielectric shows its color
+    let (n, v, l) = (Vec3::Z, Vec3::Z, glam::vec3(0.8, 0.0, 0.6));
+    let smooth_metal = SurfaceParams {
+        roughness: 0.1,
+        ..metal
+    };
+    assert!(smooth_metal.brdf(n, v, l).max_element() < 1e-3);
+    let diffuse = dielectric.brdf(n, v, l);
+    assert!((diffuse / diffuse.x - color / color.x).abs().max_element() < 0.05);
+
+    // and nothing gets lit from below
+    assert_eq!(dielectric.brdf(n, v, -l), Vec3::ZERO);
+}
+
+#[test]
+fn reciprocity_and_energy() {
+    let surface = SurfaceParams {
+        base_color: Vec3::ONE,
+        metallic: 0.0,
+        roughness: 0.4,
+    };
+    let n = Vec3::Z;
+    let v = glam::vec3(0.3, -0.2, 0.9).normalize();
+    let l = glam::vec3(-0.5, 0.4, 0.7).normalize();
+    let (forward, backward) = (surface.brdf(n, v, l), surface.brdf(n, l, v));
+    assert!(
+        forward.abs_diff_eq(backward, 1e-5),
+        "{} {}",
+        forward,
+        backward
+    );
+
+    // A white surface can't reflect more light than it receives. Light bouncing more than
+    // once between microfacets is lost, very rough metals get noticeably darker
+    for metallic in [0.0, 1.0] {
+        for roughness in [0.2, 0.6, 1.0] {
+            let surface = SurfaceParams {
+                base_color: Vec3::ONE,
+                metallic,
+                roughness,
+            };
+            let reflected = integrate_hemisphere(|l| surface.brdf(n, v, l).x * l.z);
+            assert!(
+                reflected <= 1.0 && reflected > 0.3,
+                "metallic {} roughness {}: {}",
+                metallic,
+                roughness,
+                reflected
+            );
+        }
+    }
+}
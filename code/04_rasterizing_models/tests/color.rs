@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use glam::Vec4;
+use ruster::*;
+
+#[test]
+fn srgb_transfer() {
+    for value in 0..=255u8 {
+        let linear = srgb8_to_linear(value);
+        assert!((linear - srgb_to_linear(value as f32 / 255.0)).abs() < 1e-6);
+        assert!((linear_to_srgb(linear) * 255.0 - value as f32).abs() < 1e-3);
+    }
+    // half the light is a lot brighter than half the code values
+    assert_eq!(
+        encode_srgb(glam::vec4(0.5, 0.0, 1.0, 0.5)),
+        to_argb8(128, 188, 0, 255)
+    );
+    // out of range values saturate
+    assert_eq!(
+        encode_srgb(glam::vec4(4.0, -1.0, 0.0, 1.0)),
+        to_argb8(255, 255, 0, 0)
+    );
+}
+
+fn draw_quad(target: &mut RenderTarget, settings: &RenderSettings, color: Vec4) {
+    let vertex_shader = |vertex: &Vertex| ClipVertex::new(vertex.position, 0.0);
+    let fragment_shader = |_: &Fragment<f32>| Some(color);
+    let quad = plane(glam::Vec2::ONE, 1);
+    raster_mesh(&quad, &vertex_shader, &fragment_shader, settings, target);
+}
+
+#[test]
+fn framebuffer_is_linear() {
+    let id = coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH);
+    let mut buffer = vec![0; WIDTH * HEIGHT];
+
+    // values over 1 are kept until present
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    draw_quad(
+        &mut target,
+        &RenderSettings::default(),
+        glam::vec4(4.0, 0.5, 0.0, 1.0),
+    );
+    assert_eq!(target.color[id], glam::vec4(4.0, 0.5, 0.0, 1.0));
+    target.present(&mut buffer);
+    assert_eq!(buffer[id], to_argb8(255, 255, 188, 0));
+    assert_eq!(&target.to_rgb8()[id * 3..id * 3 + 3], [255, 188, 0]);
+
+    // white half covering black blends to half the light
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    target.clear(glam::vec4(0.0, 0.0, 0.0, 1.0));
+    let settings = RenderSettings {
+        blend: BlendMode::Alpha,
+        ..Default::default()
+    };
+    draw_quad(&mut target, &settings, glam::vec4(1.0, 1.0, 1.0, 0.5));
+    assert_eq!(target.color[id], glam::vec4(0.5, 0.5, 0.5, 1.0));
+    target.present(&mut buffer);
+    assert_eq!(buffer[id], to_argb8(255, 188, 188, 188));
+}
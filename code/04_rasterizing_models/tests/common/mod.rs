@@ -0,0 +1,332 @@
+// Reference scenes shared by the integration tests
+#![allow(dead_code)]
+
+use glam::{UVec3, Vec2, Vec3};
+use ruster::*;
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 128;
+
+// every component within `tolerance`
+pub fn assert_close(a: Vec3, b: Vec3, tolerance: f32) {
+    assert!(
+        (a - b).abs().max_element() < tolerance,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+pub fn camera_at(position: Vec3) -> Camera {
+    Camera {
+        aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        transform: Transform::from_translation(position),
+        frustum_near: 0.5,
+        frustum_far: 50.0,
+        ..Default::default()
+    }
+}
+
+// unit cube centered at the origin, one color per face, counter clockwise like glTF
+pub fn cube() -> Mesh {
+    let faces = [
+        (Vec3::X, Vec3::Y, glam::vec3(1.0, 0.2, 0.2)),
+        (-Vec3::X, Vec3::Y, glam::vec3(0.2, 1.0, 0.2)),
+        (Vec3::Y, Vec3::Z, glam::vec3(0.2, 0.2, 1.0)),
+        (-Vec3::Y, Vec3::Z, glam::vec3(1.0, 1.0, 0.2)),
+        (Vec3::Z, Vec3::Y, glam::vec3(1.0, 0.2, 1.0)),
+        (-Vec3::Z, Vec3::Y, glam::vec3(0.2, 1.0, 1.0)),
+    ];
+    let mut mesh = Mesh::new();
+    for (normal, up, color) in faces {
+        let right = up.cross(normal);
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let vertices: Vec<Vertex> = corners
+            .iter()
+            .map(|(x, y)| {
+                let position = (normal + right * *x + up * *y) * 0.5;
+                let uv = glam::vec2(*x * 0.5 + 0.5, *y * 0.5 + 0.5);
+                Vertex::new(position.extend(1.0), normal, color, uv)
+            })
+            .collect();
+        mesh.add_section_from_vertices(&[UVec3::new(0, 1, 2), UVec3::new(0, 2, 3)], &vertices);
+    }
+    mesh
+}
+
+// quad on the XY plane facing +Z, split in `cells` x `cells` pairs of triangles
+pub fn plane(size: Vec2, cells: u32) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for j in 0..=cells {
+        for i in 0..=cells {
+            let uv = glam::vec2(i as f32, j as f32) / cells as f32;
+            let position = ((uv - 0.5) * size).extend(0.0).extend(1.0);
+            vertices.push(Vertex::new(position, Vec3::Z, Vec3::ONE, uv));
+        }
+    }
+    for j in 0..cells {
+        for i in 0..cells {
+            let v0 = j * (cells + 1) + i;
+            let v1 = v0 + 1;
+            let v2 = v0 + cells + 1;
+            let v3 = v2 + 1;
+            triangles.push(UVec3::new(v0, v1, v3));
+            triangles.push(UVec3::new(v0, v3, v2));
+        }
+    }
+    Mesh::from_vertices(&triangles, &vertices)
+}
+
+// unit radius uv sphere, counter clockwise, u goes around and v from pole to pole
+pub fn sphere(segments: u32, rings: u32) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for j in 0..=rings {
+        for i in 0..=segments {
+            let uv = glam::vec2(i as f32 / segments as f32, j as f32 / rings as f32);
+            let (theta, phi) = (uv.x * std::f32::consts::TAU, uv.y * std::f32::consts::PI);
+            let normal = glam::vec3(theta.sin() * phi.sin(), phi.cos(), theta.cos() * phi.sin());
+            vertices.push(Vertex::new(normal.extend(1.0), normal, Vec3::ONE, uv));
+        }
+    }
+    for j in 0..rings {
+        for i in 0..segments {
+            let v0 = j * (segments + 1) + i;
+            let v1 = v0 + 1;
+            let v2 = v0 + segments + 1;
+            let v3 = v2 + 1;
+            triangles.push(UVec3::new(v0, v2, v3));
+            triangles.push(UVec3::new(v0, v3, v1));
+        }
+    }
+    Mesh::from_vertices(&triangles, &vertices)
+}
+
+pub fn checker_texture(size: usize, cell: usize) -> Texture {
+    let data = (0..size * size)
+        .map(|id| {
+            let (x, y) = index_to_coords(id, size);
+            if (x / cell + y / cell) & 1 == 0 {
+                to_argb8(255, 230, 230, 230)
+            } else {
+                to_argb8(255, 200, 40, 40)
+            }
+        })
+        .collect();
+    Texture::new(size, size, data, 4)
+}
+
+// Writes a glTF file with a single unit quad facing +Z and the given material,
+// `material` is the JSON object that goes into the materials array and `extra`
+// more top level entries, starting with a comma (see gltf_texture)
+pub fn write_gltf_quad(name: &str, material: &str, extra: &str) -> std::path::PathBuf {
+    write_gltf_quad_nodes(name, r#"{ "mesh": 0 }"#, material, extra)
+}
+
+// Same with the given nodes array contents, node 0 is the only root
+pub fn write_gltf_quad_nodes(
+    name: &str,
+    nodes: &str,
+    material: &str,
+    extra: &str,
+) -> std::path::PathBuf {
+    let positions = [
+        [-0.5f32, -0.5, 0.0],
+        [0.5, -0.5, 0.0],
+        [0.5, 0.5, 0.0],
+        [-0.5, 0.5, 0.0],
+    ];
+    let mut buffer: Vec<u8> = Vec::new();
+    for p in positions {
+        p.iter()
+            .for_each(|v| buffer.extend_from_slice(&v.to_le_bytes()));
+    }
+    for _ in positions {
+        [0.0f32, 0.0, 1.0]
+            .iter()
+            .for_each(|v| buffer.extend_from_slice(&v.to_le_bytes()));
+    }
+    for i in [0u16, 1, 2, 0, 2, 3] {
+        buffer.extend_from_slice(&i.to_le_bytes());
+    }
+
+    let json = format!(
+        r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [{nodes}],
+  "meshes": [{{ "primitives": [{{
+    "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2, "material": 0
+  }}] }}],
+  "materials": [{material}]{extra},
+  "buffers": [{{ "byteLength": {length}, "uri": "data:application/octet-stream;base64,{data}" }}],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 48, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 96, "byteLength": 12 }}
+  ],
+  "accessors": [
+    {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+       "min": [-0.5, -0.5, 0.0], "max": [0.5, 0.5, 0.0] }},
+    {{ "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3" }},
+    {{ "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" }}
+  ]
+}}"#,
+        nodes = nodes,
+        material = material,
+        extra = extra,
+        length = buffer.len(),
+        data = base64(&buffer),
+    );
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("gltf");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.gltf", name));
+    std::fs::write(&path, json).unwrap();
+    path
+}
+
+// Writes a glTF file with a single primitive and no material. Empty `normals` or
+// `indices` leave the attribute out, neither has to match the positions
+pub fn write_gltf_triangles(
+    name: &str,
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    indices: &[u16],
+) -> std::path::PathBuf {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut attributes = Vec::new();
+    for (attribute, values) in [("POSITION", positions), ("NORMAL", normals)] {
+        if values.is_empty() {
+            continue;
+        }
+        let offset = buffer.len();
+        values
+            .iter()
+            .flatten()
+            .for_each(|v| buffer.extend_from_slice(&v.to_le_bytes()));
+        attributes.push(format!(r#""{}": {}"#, attribute, accessors.len()));
+        views.push(format!(
+            r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#,
+            offset,
+            buffer.len() - offset
+        ));
+        let (min, max) = values
+            .iter()
+            .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), v| {
+                (
+                    [0, 1, 2].map(|i| min[i].min(v[i])),
+                    [0, 1, 2].map(|i| max[i].max(v[i])),
+                )
+            });
+        accessors.push(format!(
+            r#"{{ "bufferView": {}, "componentType": 5126, "count": {}, "type": "VEC3", "min": {:?}, "max": {:?} }}"#,
+            views.len() - 1,
+            values.len(),
+            min,
+            max
+        ));
+    }
+    let mut primitive = format!(r#""attributes": {{ {} }}"#, attributes.join(", "));
+    if !indices.is_empty() {
+        let offset = buffer.len();
+        indices
+            .iter()
+            .for_each(|i| buffer.extend_from_slice(&i.to_le_bytes()));
+        views.push(format!(
+            r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#,
+            offset,
+            buffer.len() - offset
+        ));
+        primitive += &format!(r#", "indices": {}"#, accessors.len());
+        accessors.push(format!(
+            r#"{{ "bufferView": {}, "componentType": 5123, "count": {}, "type": "SCALAR" }}"#,
+            views.len() - 1,
+            indices.len()
+        ));
+    }
+    // accessors have to be 4 byte aligned
+    buffer.resize(buffer.len().div_ceil(4) * 4, 0);
+
+    let json = format!(
+        r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [{{ "mesh": 0 }}],
+  "meshes": [{{ "primitives": [{{ {primitive} }}] }}],
+  "buffers": [{{ "byteLength": {length}, "uri": "data:application/octet-stream;base64,{data}" }}],
+  "bufferViews": [{views}],
+  "accessors": [{accessors}]
+}}"#,
+        primitive = primitive,
+        length = buffer.len(),
+        data = base64(&buffer),
+        views = views.join(", "),
+        accessors = accessors.join(", "),
+    );
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("gltf");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.gltf", name));
+    std::fs::write(&path, json).unwrap();
+    path
+}
+
+// Top level entries for write_gltf_quad: texture 0 is a 1x1 white image read
+// with the given sampler
+pub fn gltf_texture(sampler: &str) -> String {
+    gltf_textures(sampler, &[[255; 4]])
+}
+
+// Same with one 1x1 rgba image per pixel, texture i shows image i
+pub fn gltf_textures(sampler: &str, pixels: &[[u8; 4]]) -> String {
+    let mut textures = Vec::new();
+    let mut images = Vec::new();
+    for (i, pixel) in pixels.iter().enumerate() {
+        let mut image = Vec::new();
+        let mut encoder = png::Encoder::new(&mut image, 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixel).unwrap();
+        writer.finish().unwrap();
+        textures.push(format!(r#"{{ "sampler": 0, "source": {} }}"#, i));
+        images.push(format!(
+            r#"{{ "uri": "data:image/png;base64,{}" }}"#,
+            base64(&image)
+        ));
+    }
+    format!(
+        r#",
+  "textures": [{}],
+  "samplers": [{}],
+  "images": [{}]"#,
+        textures.join(", "),
+        sampler,
+        images.join(", ")
+    )
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+use ruster::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const RED: Vec3 = glam::const_vec3!([1.0, 0.0, 0.0]);
+const BLUE: Vec3 = glam::const_vec3!([0.0, 0.0, 1.0]);
+const CLEAR: Vec4 = Vec4::ZERO;
+
+// flat colored quad covering the middle of the screen at a fixed ndc depth
+fn draw_quad(target: &mut RenderTarget, settings: &RenderSettings, depth: f32, color: Vec3) {
+    let vertex_shader =
+        |vertex: &Vertex| ClipVertex::new(vertex.position.xy().extend(depth).extend(1.0), 0.0);
+    let fragment_shader = |_: &Fragment<f32>| Some(color.extend(1.0));
+    let quad = plane(glam::Vec2::ONE, 1);
+    raster_mesh(&quad, &vertex_shader, &fragment_shader, settings, target);
+}
+
+fn center(target: &RenderTarget) -> (Vec4, f32) {
+    let id = coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH);
+    (target.color[id], target.depth[id])
+}
+
+fn rgba(color: Vec3) -> Vec4 {
+    color.extend(1.0)
+}
+
+fn with_compare(compare: DepthCompare) -> RenderSettings {
+    RenderSettings {
+        depth: DepthState {
+            compare,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn compare_functions() {
+    // red is drawn first and in front, blue behind it
+    let cases = [
+        (DepthCompare::Less, RED),
+        (DepthCompare::LessEqual, RED),
+        (DepthCompare::Greater, BLUE),
+        (DepthCompare::Always, BLUE),
+    ];
+    for (compare, expected) in cases {
+        let settings = with_compare(compare);
+        let mut target = RenderTarget::new(WIDTH, HEIGHT);
+        // greater needs the buffer to start at the near end
+        let clear_depth = match compare {
+            DepthCompare::Greater => f32::NEG_INFINITY,
+            _ => f32::INFINITY,
+        };
+        target.clear_with_depth(CLEAR, clear_depth);
+        draw_quad(&mut target, &settings, 0.3, RED);
+        draw_quad(&mut target, &settings, 0.6, BLUE);
+        assert_eq!(center(&target).0, rgba(expected), "{:?}", compare);
+    }
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    draw_quad(&mut target, &with_compare(DepthCompare::Never), 0.3, RED);
+    assert_eq!(center(&target), (CLEAR, f32::INFINITY));
+
+    // equal only passes where the same surface has already been drawn, like a decal
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    draw_quad(&mut target, &RenderSettings::default(), 0.3, RED);
+    draw_quad(&mut target, &with_compare(DepthCompare::Equal), 0.6, BLUE);
+    assert_eq!(center(&target).0, rgba(RED));
+    draw_quad(&mut target, &with_compare(DepthCompare::Equal), 0.3, BLUE);
+    assert_eq!(center(&target).0, rgba(BLUE));
+}
+
+#[test]
+fn depth_write_mask() {
+    let mut no_write = RenderSettings::default();
+    no_write.depth.write = false;
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    draw_quad(&mut target, &no_write, 0.3, RED);
+    assert_eq!(center(&target), (rgba(RED), f32::INFINITY));
+
+    // nothing was written, so blue still passes the depth test
+    draw_quad(&mut target, &RenderSettings::default(), 0.6, BLUE);
+    assert_eq!(center(&target), (rgba(BLUE), 0.6));
+}
+
+#[test]
+fn depth_range() {
+    let mut overlay = RenderSettings::default();
+    overlay.depth.range_near = 0.0;
+    overlay.depth.range_far = 0.1;
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    draw_quad(&mut target, &RenderSettings::default(), 0.3, RED);
+    draw_quad(&mut target, &overlay, 0.9, BLUE);
+    let (color, depth) = center(&target);
+    assert_eq!(color, rgba(BLUE));
+    assert!((depth - 0.09).abs() < 1e-6);
+}
+
+fn render_cubes(reversed_z: bool) -> RenderTarget {
+    let mut camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    camera.reversed_z = reversed_z;
+    let view_proj = camera.projection() * camera.view();
+
+    let mut settings = RenderSettings::default();
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    if reversed_z {
+        settings.depth = DepthState::reversed_z();
+        target.clear_with_depth(CLEAR, 0.0);
+    }
+    // two cubes going through each other
+    for (translation, angle) in [(Vec3::ZERO, 0.4), (glam::vec3(0.4, 0.2, -0.3), -0.7)] {
+        let model = Mat4::from_translation(translation) * Mat4::from_rotation_y(angle);
+        let material = Material::default();
+        let shader = DefaultShader::new(&model, &(view_proj * model), &material);
+        raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    }
+    target
+}
+
+#[test]
+fn reversed_z_matches_regular_depth() {
+    let regular = render_cubes(false);
+    let reversed = render_cubes(true);
+    assert!(regular.color.iter().any(|color| *color != CLEAR));
+    assert!(regular.color == reversed.color);
+
+    // closer is greater now
+    let id = coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH);
+    assert!(reversed.depth[id] > 0.0 && reversed.depth[id] < 1.0);
+}
+
+#[test]
+fn depth_only() {
+    let settings = RenderSettings {
+        depth_only: true,
+        ..Default::default()
+    };
+    let shaded = AtomicUsize::new(0);
+    let vertex_shader =
+        |vertex: &Vertex| ClipVertex::new(vertex.position.xy().extend(0.3).extend(1.0), 0.0);
+    let fragment_shader = |_: &Fragment<f32>| {
+        shaded.fetch_add(1, Ordering::Relaxed);
+        Some(Vec4::ZERO)
+    };
+    let quad = plane(glam::Vec2::ONE, 1);
+    let id = coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH);
+
+    // no color buffer needed, and nothing to shade
+    let mut target = RenderTarget::depth_only(WIDTH, HEIGHT);
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    assert!(target.color.is_empty());
+    assert_eq!(target.depth[id], 0.3);
+    assert_eq!(shaded.load(Ordering::Relaxed), 0);
+
+    let mut tiled = RenderTarget::depth_only(WIDTH, HEIGHT);
+    raster_mesh_tiled(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut tiled,
+    );
+    assert!(tiled.depth == target.depth);
+
+    // cutouts still need the shader for their alpha, but color stays untouched
+    let cutout = RenderSettings {
+        alpha_cutoff: Some(0.5),
+        ..settings
+    };
+    let mut target = RenderTarget::depth_only(WIDTH, HEIGHT);
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &cutout,
+        &mut target,
+    );
+    assert!(shaded.load(Ordering::Relaxed) > 0);
+    assert_eq!(target.depth[id], f32::INFINITY);
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3};
+use ruster::environment::{equirect_direction, equirect_uv};
+use ruster::*;
+use std::f32::consts::PI;
+
+// equirectangular float texture, `radiance` gets called with every texel's direction
+fn environment(width: usize, radiance: impl Fn(Vec3) -> Vec3) -> Texture {
+    let height = width / 2;
+    let mut data = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let uv = glam::vec2(
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            );
+            data.push(radiance(equirect_direction(uv)).extend(1.0));
+        }
+    }
+    Texture::new_float(width, height, data, 4)
+}
+
+// sky above the horizon, dark ground below
+fn sky(direction: Vec3) -> Vec3 {
+    if direction.y > 0.0 {
+        glam::vec3(0.5, 0.7, 1.0)
+    } else {
+        Vec3::splat(0.1)
+    }
+}
+
+#[test]
+fn equirect_mapping() {
+    assert_close(
+        equirect_uv(-Vec3::Z).extend(0.0),
+        glam::vec3(0.5, 0.5, 0.0),
+        1e-5,
+    );
+    assert!(equirect_uv(Vec3::Y).y < 1e-5);
+    assert!(equirect_uv(-Vec3::Y).y > 1.0 - 1e-5);
+    for direction in [
+        Vec3::X,
+        -Vec3::X,
+        glam::vec3(0.3, 0.5, -0.8),
+        glam::vec3(-0.1, -0.9, 0.4),
+    ] {
+        let direction = direction.normalize();
+        assert_close(equirect_direction(equirect_uv(direction)), direction, 1e-4);
+    }
+}
+
+#[test]
+fn constant_environment() {
+    // the "furnace test": a uniform white environment
+    let map = EnvironmentMap::new(environment(64, |_| Vec3::ONE));
+    for n in [Vec3::Y, -Vec3::Z, glam::vec3(0.6, -0.3, 0.2).normalize()] {
+        // cosine weighted over the hemisphere
+        assert_close(map.irradiance.irradiance(n), Vec3::splat(PI), 0.02);
+        for roughness in [0.0, 0.3, 1.0] {
+            assert_close(map.specular(n, roughness), Vec3::ONE, 0.02);
+        }
+    }
+    // a white surface reflects about all of it
+    let surface = SurfaceParams {
+        base_color: Vec3::ONE,
+        metallic: 1.0,
+        roughness: 0.2,
+    };
+    let reflected = map.shade(&surface, Vec3::Y, glam::vec3(0.0, 1.0, 1.0).normalize());
+    assert_close(reflected, Vec3::ONE, 0.05);
+}
+
+#[test]
+fn sky_irradiance() {
+    let map = EnvironmentMap::new(environment(64, sky));
+    let up = map.irradiance.irradiance(Vec3::Y);
+    let down = map.irradiance.irradiance(-Vec3::Y);
+    let side = map.irradiance.irradiance(Vec3::X);
+    // facing straight up sees nothing but sky, 3 bands of SH can't be exact
+    assert_close(up, sky(Vec3::Y) * PI, 0.15);
+    assert_close(down, sky(-Vec3::Y) * PI, 0.15);
+    // half and half
+    assert_close(side, (sky(Vec3::Y) + sky(-Vec3::Y)) * 0.5 * PI, 0.05);
+}
+
+#[test]
+fn prefiltered_specular() {
+    let map = EnvironmentMap::new(environment(128, sky));
+    // a mirror sees the image as is
+    let up = glam::vec3(0.3, 0.8, 0.2).normalize();
+    assert_close(map.specular(up, 0.0), sky(up), 1e-3);
+    // rougher reflections blur the horizon
+    let horizon = glam::vec3(1.0, 0.02, 0.0).normalize();
+    let sharp = map.specular(horizon, 0.0);
+    let blurry = map.specular(horizon, 1.0);
+    assert!(blurry.z < sharp.z - 0.1, "{:?} {:?}", sharp, blurry);
+    assert!(blurry.z > 0.2, "{:?}", blurry);
+}
+
+#[test]
+fn brdf_lut() {
+    let lut = BrdfLut::new(16, 256);
+    for (n_dot_v, roughness) in [(1.0, 0.0), (0.5, 0.5), (0.1, 0.9), (0.9, 1.0)] {
+        let value = lut.get(n_dot_v, roughness);
+        // energy can only get lost
+        assert!(value.x > 0.0 && value.y >= 0.0, "{:?}", value);
+        assert!(value.x + value.y <= 1.01, "{:?}", value);
+    }
+    // straight on a smooth surface there is no fresnel and nothing gets lost
+    let smooth = lut.get(1.0, 0.0);
+    assert!(smooth.x > 0.95 && smooth.y < 0.02, "{:?}", smooth);
+    // at grazing angles fresnel takes over
+    let grazing = lut.get(0.05, 0.0);
+    assert!(grazing.y > smooth.y, "{:?}", grazing);
+}
+
+// the environment replaces the constant ambient
+#[test]
+fn shaders_use_the_environment() {
+    let map = EnvironmentMap::new(environment(32, |_| Vec3::splat(2.0)));
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    // the default, a rough metal, loses a good part of the light (see brdf.rs)
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 0.5,
+        ..Default::default()
+    };
+    let mesh = plane(glam::vec2(2.0, 2.0), 1);
+    let center = |target: &RenderTarget| target.color[HEIGHT / 2 * WIDTH + WIDTH / 2];
+
+    let eye = camera.transform.translation;
+    let render_pbr = |environment| {
+        let mut target = RenderTarget::new(WIDTH, HEIGHT);
+        let shader = PbrShader {
+            environment,
+            ..PbrShader::new(&Mat4::IDENTITY, &view_proj, eye, &material, &[])
+        };
+        raster_mesh(
+            &mesh,
+            &shader,
+            &shader,
+            &RenderSettings::default(),
+            &mut target,
+        );
+        center(&target)
+    };
+    let constant = render_pbr(None);
+    let lit = render_pbr(Some(&map));
+    assert!(lit.x > constant.x * 10.0, "{:?} {:?}", lit, constant);
+    // white furnace times 2, about all of it comes back
+    assert_close(lit.truncate(), Vec3::splat(2.0), 0.1);
+
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let shader = DefaultShader {
+        environment: Some(&map),
+        light_dir: -Vec3::Z,
+        ..DefaultShader::new(&Mat4::IDENTITY, &view_proj, &material)
+    };
+    raster_mesh(
+        &mesh,
+        &shader,
+        &shader,
+        &RenderSettings::default(),
+        &mut target,
+    );
+    assert_close(center(&target).truncate(), Vec3::splat(2.0), 0.05);
+    assert_eq!(center(&target).w, 1.0);
+}
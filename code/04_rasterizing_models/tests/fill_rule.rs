@@ -0,0 +1,87 @@
+// Adjacent triangles of a mesh have to cover every pixel exactly once
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec4, Vec4Swizzles};
+use ruster::*;
+use std::cell::RefCell;
+
+// How many times every pixel got shaded. Fragments are discarded after being counted,
+// so nothing gets written to the depth buffer and overlaps can't hide behind the depth test
+fn coverage(mesh: &Mesh, mvp: Mat4) -> Vec<u32> {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let counts = RefCell::new(vec![0; WIDTH * HEIGHT]);
+    let vertex_shader =
+        |vertex: &Vertex| ClipVertex::new(mvp * vertex.position.xyz().extend(1.0), 0.0);
+    let fragment_shader = |fragment: &Fragment<f32>| -> Option<Vec4> {
+        let x = fragment.coords.x as usize;
+        let y = fragment.coords.y as usize;
+        counts.borrow_mut()[coords_to_index(x, y, WIDTH)] += 1;
+        None
+    };
+    let settings = RenderSettings::default();
+    raster_mesh(
+        mesh,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    counts.into_inner()
+}
+
+// clip space x, y of a screen position, for a target of WIDTH x HEIGHT
+fn screen_to_clip(x: f32, y: f32) -> glam::Vec2 {
+    glam::vec2(x / WIDTH as f32 * 2.0 - 1.0, 1.0 - y / HEIGHT as f32 * 2.0)
+}
+
+#[test]
+fn edges_through_pixel_centers() {
+    // every vertex lands on a pixel center, so do the edges between them,
+    // the quad goes from the center of pixel 16 to the center of pixel 112
+    let min = screen_to_clip(16.5, 112.5);
+    let max = screen_to_clip(112.5, 16.5);
+    let model = Mat4::from_translation(((min + max) * 0.5).extend(0.5))
+        * Mat4::from_scale((max - min).extend(1.0));
+    let counts = coverage(&plane(glam::Vec2::ONE, 12), model);
+
+    // top and left edges are in, bottom and right edges are out
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let expected = ((16..112).contains(&x) && (16..112).contains(&y)) as u32;
+            let count = counts[coords_to_index(x, y, WIDTH)];
+            assert_eq!(count, expected, "pixel ({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn tessellated_quad_is_watertight() {
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    let model = Mat4::from_rotation_z(0.37) * Mat4::from_rotation_x(-0.6);
+    let mvp = view_proj * model;
+    let size = glam::vec2(2.3, 1.7);
+
+    let whole = coverage(&plane(size, 1), mvp);
+    let tessellated = coverage(&plane(size, 23), mvp);
+    assert!(whole.contains(&1));
+    assert!(whole.iter().all(|count| *count <= 1));
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let count = tessellated[coords_to_index(x, y, WIDTH)];
+            assert!(count <= 1, "pixel ({}, {}) drawn {} times", x, y, count);
+
+            // the outline moves a little when the vertices along it get snapped,
+            // so only pixels well inside the quad have to be covered
+            let well_inside = (y.max(1) - 1..=(y + 1).min(HEIGHT - 1)).all(|ny| {
+                (x.max(1) - 1..=(x + 1).min(WIDTH - 1))
+                    .all(|nx| whole[coords_to_index(nx, ny, WIDTH)] == 1)
+            });
+            if well_inside {
+                assert_eq!(count, 1, "hole at pixel ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::*;
+use glam::Vec3;
+use ruster::*;
+
+// two triangles sharing an edge, one facing +Z and one facing (-1, 1, 0)
+const FOLDED: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [1.0, 1.0, -1.0],
+];
+
+#[test]
+fn flat_normals_without_normal_attribute() {
+    let path = write_gltf_triangles("no_normals", &FOLDED, &[], &[0, 1, 2, 0, 2, 3]);
+    let mesh = &load_gltf_models(&path).unwrap()[0].mesh;
+    // shared vertices get split, every triangle faces its own way
+    assert_eq!(mesh.triangles().len(), 2);
+    assert_eq!(mesh.vertices().len(), 6);
+    let expected = [Vec3::Z, glam::vec3(-1.0, 1.0, 0.0).normalize()];
+    for (triangle, normal) in mesh.triangles().iter().zip(expected) {
+        for vertex in mesh.get_vertices_from_triangle(*triangle) {
+            assert_close(vertex.normal, normal, 1e-6);
+        }
+    }
+}
+
+#[test]
+fn non_indexed() {
+    let positions = [
+        FOLDED[0], FOLDED[1], FOLDED[2], FOLDED[0], FOLDED[2], FOLDED[3],
+    ];
+    let normals = [[0.0, 0.0, 1.0]; 6];
+    let path = write_gltf_triangles("non_indexed", &positions, &normals, &[]);
+    let mesh = &load_gltf_models(&path).unwrap()[0].mesh;
+    assert_eq!(
+        mesh.triangles(),
+        &vec![glam::uvec3(0, 1, 2), glam::uvec3(3, 4, 5)]
+    );
+    assert_eq!(mesh.vertices().len(), 6);
+}
+
+#[test]
+fn mismatched_accessors() {
+    let path = write_gltf_triangles("short_normals", &FOLDED, &[[0.0, 0.0, 1.0]; 3], &[0, 1, 2]);
+    let error = load_gltf_models(&path).err().unwrap();
+    assert!(
+        matches!(
+            error,
+            SceneError::Mesh(MeshError::AttributeLength {
+                attribute: "NORMAL",
+                expected: 4,
+                found: 3
+            })
+        ),
+        "{}",
+        error
+    );
+
+    let path = write_gltf_triangles("bad_index", &FOLDED, &[], &[0, 1, 4]);
+    let error = load_gltf_models(&path).err().unwrap();
+    assert!(
+        matches!(
+            error,
+            SceneError::Mesh(MeshError::IndexOutOfRange {
+                index: 4,
+                vertices: 4
+            })
+        ),
+        "{}",
+        error
+    );
+}
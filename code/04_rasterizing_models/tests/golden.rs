@@ -0,0 +1,375 @@
+// Golden image regression tests: every scene is rendered offscreen and compared
+// against the reference png in tests/golden. On a mismatch the actual render and a
+// diff image (red = pixel out of tolerance) are written next to the test binaries.
+//
+// After an intended change to the output regenerate the references with:
+//   RUSTER_BLESS=1 cargo test --test golden
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3};
+use ruster::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// max difference allowed on a single color channel
+const TOLERANCE: u8 = 2;
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+fn load_rgb8(path: &Path) -> (usize, usize, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgb, "{:?}", path);
+    assert_eq!(info.bit_depth, png::BitDepth::Eight, "{:?}", path);
+    data.truncate(info.buffer_size());
+    (info.width as usize, info.height as usize, data)
+}
+
+fn assert_golden(name: &str, target: &RenderTarget) {
+    let path = golden_path(name);
+    if std::env::var_os("RUSTER_BLESS").is_some() {
+        target.save_png(&path).unwrap();
+        return;
+    }
+    assert!(
+        path.exists(),
+        "missing golden image {:?}, run with RUSTER_BLESS=1 to create it",
+        path
+    );
+
+    let (width, height, expected) = load_rgb8(&path);
+    assert_eq!((width, height), (target.width, target.height));
+    let actual = target.to_rgb8();
+
+    let mut diff = RenderTarget::new(width, height);
+    let mut mismatches = 0;
+    for (id, pixel) in diff.color.iter_mut().enumerate() {
+        let e = &expected[id * 3..id * 3 + 3];
+        let a = &actual[id * 3..id * 3 + 3];
+        if e.iter().zip(a).any(|(e, a)| e.abs_diff(*a) > TOLERANCE) {
+            mismatches += 1;
+            *pixel = glam::vec4(1.0, 0.0, 0.0, 1.0);
+        } else {
+            // faded copy of the reference so the failing region is easy to locate
+            let luma = (e[0] as f32 + e[1] as f32 + e[2] as f32) / (9.0 * 255.0);
+            *pixel = Vec3::splat(srgb_to_linear(luma)).extend(1.0);
+        }
+    }
+
+    if mismatches > 0 {
+        let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{}.actual.png", name));
+        let diff_path = out_dir.join(format!("{}.diff.png", name));
+        target.save_png(&actual_path).unwrap();
+        diff.save_png(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels differ from {:?} (tolerance {}), see {:?} and {:?}",
+            name, mismatches, path, TOLERANCE, actual_path, diff_path
+        );
+    }
+}
+
+#[test]
+fn golden_cube() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let model =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, 0.6, 0.8, 0.0))
+            .local();
+    let mvp = camera.projection() * camera.view() * model;
+
+    let material = Material::default();
+    let shader = DefaultShader::new(&model, &mvp, &material);
+    raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    assert_golden("cube", &target);
+}
+
+#[test]
+fn golden_textured_cube() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let material = Material::from_texture(Arc::new(checker_texture(16, 4)));
+    let camera = camera_at(glam::vec3(0.0, 0.0, 2.5));
+    let model =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, -0.4, 2.5, 0.0))
+            .local();
+    let mvp = camera.projection() * camera.view() * model;
+
+    let shader = DefaultShader::new(&model, &mvp, &material);
+    raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    assert_golden("textured_cube", &target);
+}
+
+// low wall running from far away to behind the camera, crosses the near plane
+#[test]
+fn golden_near_clipping() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let camera = camera_at(Vec3::ZERO);
+    let model = Mat4::from_translation(-0.5 * Vec3::X)
+        * Mat4::from_rotation_y(1.1)
+        * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let mvp = camera.projection() * camera.view() * model;
+
+    let wall = plane(glam::vec2(0.4, 40.0), 2);
+    let material = Material::default();
+    let shader = DefaultShader::new(&model, &mvp, &material);
+    raster_mesh(&wall, &shader, &shader, &settings, &mut target);
+    assert_golden("near_clipping", &target);
+}
+
+// floor under the camera and a cube cut by the left and top edges of the screen,
+// exercises clipping against all the frustum planes
+#[test]
+fn golden_frustum_clipping() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let material = Material::from_texture(Arc::new(checker_texture(64, 4)));
+    let mut camera = camera_at(glam::vec3(0.0, 1.0, 0.0));
+    camera.transform.rotation = glam::Quat::from_rotation_x(-0.3);
+    let view_proj = camera.projection() * camera.view();
+
+    let model = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let shader = DefaultShader::new(&model, &(view_proj * model), &material);
+    let floor = plane(glam::vec2(40.0, 40.0), 2);
+    raster_mesh(&floor, &shader, &shader, &settings, &mut target);
+
+    let model = Mat4::from_translation(glam::vec3(-1.1, 1.2, -3.5)) * Mat4::from_rotation_y(0.5);
+    let untextured = Material::default();
+    let shader = DefaultShader::new(&model, &(view_proj * model), &untextured);
+    raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    assert_golden("frustum_clipping", &target);
+}
+
+#[test]
+fn golden_clip_debug() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let mut camera = camera_at(glam::vec3(0.0, 1.0, 0.0));
+    camera.transform.rotation = glam::Quat::from_rotation_x(-0.3);
+    let model = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let mvp = camera.projection() * camera.view() * model;
+
+    let settings = RenderSettings {
+        clip_debug: true,
+        ..Default::default()
+    };
+    let material = Material::default();
+    let shader = DefaultShader::new(&model, &mvp, &material);
+    raster_mesh(
+        &plane(glam::vec2(40.0, 40.0), 4),
+        &shader,
+        &shader,
+        &settings,
+        &mut target,
+    );
+    assert_golden("clip_debug", &target);
+}
+
+// two overlapping triangles, checks depth testing and raster_triangle on its own
+#[test]
+fn golden_triangles() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+
+    let red = Vertex::new(Vec3::ZERO.extend(1.0), Vec3::Z, Vec3::X, glam::Vec2::ZERO);
+    let v0 = Vertex {
+        position: glam::vec4(-1.0, -1.0, 0.0, 1.0),
+        ..red
+    };
+    let v1 = Vertex {
+        position: glam::vec4(1.0, -0.5, 0.0, 1.0),
+        color: Vec3::Y,
+        ..red
+    };
+    let v2 = Vertex {
+        position: glam::vec4(0.0, 1.0, 0.0, 1.0),
+        color: Vec3::Z,
+        ..red
+    };
+    let material = Material::default();
+    let shader = DefaultShader::new(&Mat4::IDENTITY, &view_proj, &material);
+    raster_triangle(&[&v0, &v1, &v2], &shader, &shader, &settings, &mut target);
+
+    // tilted through the first one
+    let model = Mat4::from_rotation_y(1.0) * Mat4::from_scale(Vec3::splat(0.8));
+    let shader = DefaultShader::new(&model, &(view_proj * model), &material);
+    raster_triangle(&[&v0, &v1, &v2], &shader, &shader, &settings, &mut target);
+    assert_golden("triangles", &target);
+}
+
+#[derive(Debug, Copy, Clone)]
+struct CustomVaryings {
+    color: Vec3,
+    uv: glam::Vec2,
+}
+
+impl_varyings!(CustomVaryings { color, uv });
+
+// closures as shaders with their own varyings: unlit, with a discard pattern punched
+// through the cube
+// rough to smooth left to right, dielectric on top and metal below
+#[test]
+fn golden_pbr_spheres() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let camera = camera_at(glam::vec3(0.0, 0.0, 6.0));
+    let view_proj = camera.projection() * camera.view();
+    let sphere = sphere(24, 12);
+    let lights = [Light::Directional {
+        direction: -Vec3::ONE.normalize(),
+        color: Vec3::ONE,
+        intensity: std::f32::consts::PI,
+    }];
+    for (row, metallic) in [(0.5, 0.0), (-0.5, 1.0)] {
+        for (column, roughness) in [(-1.0, 0.9), (0.0, 0.5), (1.0, 0.2)] {
+            let material = Material {
+                base_color_factor: glam::vec4(0.9, 0.4, 0.1, 1.0),
+                metallic_factor: metallic,
+                roughness_factor: roughness,
+                ..Default::default()
+            };
+            let model = Mat4::from_translation(glam::vec3(column, row, 0.0))
+                * Mat4::from_scale(Vec3::splat(0.45));
+            let eye = camera.transform.translation;
+            let shader = PbrShader::new(&model, &view_proj, eye, &material, &lights);
+            raster_mesh(&sphere, &shader, &shader, &settings, &mut target);
+        }
+    }
+    assert_golden("pbr_spheres", &target);
+}
+
+// sphere over a floor, lit from the side so the shadow falls next to it
+#[test]
+fn golden_shadows() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let mut camera = camera_at(glam::vec3(0.0, 2.0, 4.0));
+    camera.transform.rotation = glam::Quat::from_rotation_x(-0.45);
+    let view_proj = camera.projection() * camera.view();
+    let eye = camera.transform.translation;
+    let lights = [Light::Directional {
+        direction: glam::vec3(-1.0, -1.5, -0.5).normalize(),
+        color: Vec3::ONE,
+        intensity: std::f32::consts::PI,
+    }];
+    // low resolution map, the default bias leaves acne all over the sphere
+    let shadow_settings = ShadowSettings {
+        resolution: 256,
+        depth_bias: 0.02,
+        ..Default::default()
+    };
+    let mut shadow_maps = [ShadowMap::for_light(
+        &lights[0],
+        Vec3::ZERO,
+        3.0,
+        shadow_settings,
+    )];
+
+    let floor = plane(glam::vec2(6.0, 6.0), 4);
+    let floor_model = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let sphere = sphere(24, 12);
+    let sphere_model =
+        Mat4::from_translation(glam::vec3(0.0, 0.8, 0.0)) * Mat4::from_scale(Vec3::splat(0.6));
+    for shadow_map in shadow_maps.iter_mut().flatten() {
+        shadow_map.render_mesh(&floor, &floor_model);
+        shadow_map.render_mesh(&sphere, &sphere_model);
+    }
+
+    let material = Material::default();
+    for (mesh, model) in [(&floor, floor_model), (&sphere, sphere_model)] {
+        let shader = PbrShader {
+            shadow_maps: &shadow_maps,
+            ..PbrShader::new(&model, &view_proj, eye, &material, &lights)
+        };
+        raster_mesh(mesh, &shader, &shader, &settings, &mut target);
+    }
+    assert_golden("shadows", &target);
+}
+
+// cube in front of an equirectangular sky: blue gradient above the horizon, with a
+// sun to the left, and a darker ground with a checker to see the perspective
+#[test]
+fn golden_background() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let mut camera = camera_at(glam::vec3(0.0, 0.5, 3.0));
+    camera.transform.rotation = glam::Quat::from_rotation_x(-0.2);
+    let view_proj = camera.projection() * camera.view();
+
+    let (width, height) = (128, 64);
+    let mut data = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let uv = glam::vec2(
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            );
+            let d = ruster::environment::equirect_direction(uv);
+            let color = if d.y > 0.0 {
+                let sun = d
+                    .dot(glam::vec3(-0.25, 0.3, -0.9).normalize())
+                    .max(0.0)
+                    .powi(64);
+                lerp(glam::vec3(0.6, 0.8, 1.0), glam::vec3(0.1, 0.3, 0.8), d.y) + Vec3::ONE * sun
+            } else if (x / 8 + y / 8) % 2 == 0 {
+                Vec3::splat(0.2)
+            } else {
+                Vec3::splat(0.1)
+            };
+            data.push(color.extend(1.0));
+        }
+    }
+    let sky = Texture::new_float(width, height, data, 4);
+
+    let model = Mat4::from_rotation_y(0.6) * Mat4::from_scale(Vec3::splat(0.7));
+    let material = Material::default();
+    let shader = DefaultShader::new(&model, &(view_proj * model), &material);
+    raster_mesh(&cube(), &shader, &shader, &settings, &mut target);
+    Background::Equirect(&sky).draw(&view_proj, f32::INFINITY, &mut target);
+    assert_golden("background", &target);
+}
+
+#[test]
+fn golden_custom_shader() {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let settings = RenderSettings::default();
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let model = Mat4::from_rotation_y(0.7) * Mat4::from_rotation_x(0.5);
+    let mvp = camera.projection() * camera.view() * model;
+
+    let vertex_shader = |vertex: &Vertex| {
+        let varyings = CustomVaryings {
+            color: vertex.normal * 0.5 + 0.5,
+            uv: vertex.uv,
+        };
+        ClipVertex::new(mvp * vertex.position, varyings)
+    };
+    let fragment_shader = |fragment: &Fragment<CustomVaryings>| {
+        let cell = (fragment.varyings.uv * 4.0).floor();
+        if (cell.x + cell.y) as u32 & 1 == 0 {
+            None
+        } else {
+            Some(fragment.varyings.color.extend(1.0))
+        }
+    };
+    raster_mesh(
+        &cube(),
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+    assert_golden("custom_shader", &target);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec2, Vec3};
+use ruster::*;
+
+#[test]
+fn directional() {
+    let sun = Light::Directional {
+        direction: glam::vec3(0.0, -2.0, 0.0),
+        color: glam::vec3(1.0, 0.5, 0.25),
+        intensity: 4.0,
+    };
+    // the same everywhere
+    for position in [Vec3::ZERO, glam::vec3(100.0, -5.0, 3.0)] {
+        let (l, radiance) = sun.incident(position);
+        assert_eq!(l, Vec3::Y);
+        assert_eq!(radiance, glam::vec3(4.0, 2.0, 1.0));
+    }
+}
+
+#[test]
+fn point_attenuation() {
+    let light = |range| Light::Point {
+        position: glam::vec3(0.0, 2.0, 0.0),
+        color: Vec3::ONE,
+        intensity: 8.0,
+        range,
+    };
+    let (l, radiance) = light(None).incident(Vec3::ZERO);
+    assert_eq!(l, Vec3::Y);
+    assert_eq!(radiance, Vec3::splat(2.0));
+    // twice as far, a quarter of the light
+    let (_, radiance) = light(None).incident(glam::vec3(0.0, -2.0, 0.0));
+    assert_eq!(radiance, Vec3::splat(0.5));
+
+    // with a range it fades out smoothly and is gone past it
+    let (_, near) = light(Some(10.0)).incident(glam::vec3(0.0, 1.0, 0.0));
+    assert!((near.x - 8.0).abs() < 0.01, "{}", near);
+    let (_, edge) = light(Some(10.0)).incident(glam::vec3(0.0, -7.5, 0.0));
+    assert!(edge.x > 0.0 && edge.x < 8.0 / 90.25 * 0.5, "{}", edge);
+    let (_, past) = light(Some(10.0)).incident(glam::vec3(0.0, -9.0, 0.0));
+    assert_eq!(past, Vec3::ZERO);
+}
+
+#[test]
+fn spot_cone() {
+    let spot = Light::Spot {
+        position: glam::vec3(0.0, 1.0, 0.0),
+        direction: -Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 1.0,
+        range: None,
+        inner_cone_angle: 0.3,
+        outer_cone_angle: 0.6,
+    };
+    // one unit below, at increasing angles from the axis
+    let at_angle = |angle: f32| spot.incident(glam::vec3(angle.tan(), 0.0, 0.0)).1.x;
+    let straight = at_angle(0.0);
+    assert_eq!(straight, 1.0);
+    // inner cone: only the distance makes a difference
+    let inside = at_angle(0.25);
+    assert!((inside - 0.25f32.cos().powi(2)).abs() < 1e-5, "{}", inside);
+    let falloff = at_angle(0.45);
+    assert!(falloff > 0.0 && falloff < 0.45f32.cos().powi(2) * 0.9);
+    assert_eq!(at_angle(0.7), 0.0);
+}
+
+#[test]
+fn pbr_shader_adds_up_lights() {
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 1.0,
+        ..Default::default()
+    };
+    let eye = glam::vec3(0.0, 0.0, 3.0);
+    let camera = camera_at(eye);
+    let view_proj = camera.projection() * camera.view();
+    let quad = plane(Vec2::ONE, 1);
+    let render = |lights: &[Light]| {
+        let mut target = RenderTarget::new(WIDTH, HEIGHT);
+        let mut shader = PbrShader::new(&Mat4::IDENTITY, &view_proj, eye, &material, lights);
+        shader.ambient = Vec3::ZERO;
+        raster_mesh(
+            &quad,
+            &shader,
+            &shader,
+            &RenderSettings::default(),
+            &mut target,
+        );
+        target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)].truncate()
+    };
+    let front = Light::Point {
+        position: glam::vec3(0.0, 0.0, 1.0),
+        color: Vec3::X,
+        intensity: 1.0,
+        range: None,
+    };
+    let side = Light::Directional {
+        direction: glam::vec3(-1.0, 0.0, -1.0),
+        color: Vec3::Z,
+        intensity: 2.0,
+    };
+    let behind = Light::Directional {
+        direction: Vec3::Z,
+        color: Vec3::ONE,
+        intensity: 100.0,
+    };
+    assert_eq!(render(&[]), Vec3::ZERO);
+    let both = render(&[front, side, behind]);
+    assert!(both.x > 0.0 && both.y == 0.0 && both.z > 0.0, "{}", both);
+    assert!(both.abs_diff_eq(render(&[front]) + render(&[side]), 1e-5));
+}
+
+#[test]
+fn gltf_lights() {
+    let json = r#"{
+  "asset": { "version": "2.0" },
+  "extensionsUsed": ["KHR_lights_punctual"],
+  "extensions": { "KHR_lights_punctual": { "lights": [
+    { "type": "directional", "color": [1.0, 0.5, 0.5], "intensity": 2.0 },
+    { "type": "point", "intensity": 10.0, "range": 5.0 },
+    { "type": "spot", "spot": { "innerConeAngle": 0.2, "outerConeAngle": 0.4 } }
+  ] } },
+  "scene": 0,
+  "scenes": [{ "nodes": [0, 1] }],
+  "nodes": [
+    { "rotation": [-0.7071068, 0.0, 0.0, 0.7071068],
+      "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+    { "translation": [0.0, 3.0, 0.0], "children": [2],
+      "extensions": { "KHR_lights_punctual": { "light": 1 } } },
+    { "translation": [1.0, 0.0, 0.0],
+      "extensions": { "KHR_lights_punctual": { "light": 2 } } }
+  ]
+}"#;
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("gltf");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lights.gltf");
+    std::fs::write(&path, json).unwrap();
+
+    let scene = load_gltf_scene(&path).unwrap();
+    assert!(scene.models.is_empty());
+    assert_eq!(scene.lights.len(), 3);
+    // rotated to point down
+    match scene.lights[0] {
+        Light::Directional {
+            direction,
+            color,
+            intensity,
+        } => {
+            assert!(direction.abs_diff_eq(-Vec3::Y, 1e-5), "{}", direction);
+            assert_eq!((color, intensity), (glam::vec3(1.0, 0.5, 0.5), 2.0));
+        }
+        light => panic!("{:?}", light),
+    }
+    assert_eq!(
+        scene.lights[1],
+        Light::Point {
+            position: glam::vec3(0.0, 3.0, 0.0),
+            color: Vec3::ONE,
+            intensity: 10.0,
+            range: Some(5.0),
+        }
+    );
+    // child nodes inherit the transform, spots point down -Z
+    assert_eq!(
+        scene.lights[2],
+        Light::Spot {
+            position: glam::vec3(1.0, 3.0, 0.0),
+            direction: -Vec3::Z,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        }
+    );
+}
+
+#[test]
+fn gltf_node_transforms() {
+    // the quad and a point light on a child of a moved and scaled node
+    let nodes = r#"{ "translation": [0.0, 0.0, -3.0], "scale": [2.0, 2.0, 2.0], "children": [1] },
+    { "translation": [1.0, 0.0, 0.0], "mesh": 0,
+      "extensions": { "KHR_lights_punctual": { "light": 0 } } }"#;
+    let extra = r#",
+  "extensionsUsed": ["KHR_lights_punctual"],
+  "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point" }] } }"#;
+    let path = write_gltf_quad_nodes("node_transforms", nodes, "{}", extra);
+    let scene = load_gltf_scene(&path).unwrap();
+
+    assert_eq!(scene.models.len(), 1);
+    let transform = scene.models[0].transform;
+    let expected =
+        Mat4::from_translation(glam::vec3(2.0, 0.0, -3.0)) * Mat4::from_scale(Vec3::splat(2.0));
+    assert!(transform.abs_diff_eq(expected, 1e-6), "{}", transform);
+    // the quad's corner ends up where the node puts it
+    let corner = transform.transform_point3(glam::vec3(0.5, 0.5, 0.0));
+    assert!(
+        corner.abs_diff_eq(glam::vec3(3.0, 1.0, -3.0), 1e-6),
+        "{}",
+        corner
+    );
+
+    assert_eq!(
+        scene.lights,
+        [Light::Point {
+            position: glam::vec3(2.0, 0.0, -3.0),
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+        }]
+    );
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use ruster::*;
+use std::sync::Arc;
+
+#[test]
+fn gltf_material() {
+    let material = r#"{
+    "pbrMetallicRoughness": {
+      "baseColorFactor": [0.5, 1.0, 1.0, 0.8],
+      "baseColorTexture": { "index": 0 },
+      "metallicFactor": 0.5,
+      "roughnessFactor": 0.25,
+      "metallicRoughnessTexture": { "index": 1 }
+    },
+    "normalTexture": { "index": 2, "scale": 0.5 },
+    "occlusionTexture": { "index": 1, "strength": 0.5 },
+    "emissiveFactor": [1.0, 0.5, 0.0],
+    "emissiveTexture": { "index": 0 }
+  }"#;
+    let pixels = [
+        [188, 188, 188, 255],
+        [0, 188, 255, 255],
+        [128, 128, 255, 255],
+    ];
+    let path = write_gltf_quad("material", material, &gltf_textures("{}", &pixels));
+    let material = &load_gltf_models(&path).unwrap()[0].material;
+    let (uv, d) = (Vec2::splat(0.5), Vec2::ZERO);
+
+    // colors are decoded from sRGB, 188 is about half the light
+    let base_color = material.base_color(uv, d, d);
+    assert!(
+        base_color.abs_diff_eq(glam::vec4(0.25, 0.5, 0.5, 0.8), 3e-3),
+        "{}",
+        base_color
+    );
+    let emissive = material.emissive(uv, d, d);
+    assert!(
+        emissive.abs_diff_eq(glam::vec3(0.5, 0.25, 0.0), 3e-3),
+        "{}",
+        emissive
+    );
+
+    // data is not
+    let (metallic, roughness) = material.metallic_roughness(uv, d, d);
+    assert!((metallic - 0.5).abs() < 1e-6);
+    assert!((roughness - 0.25 * 188.0 / 255.0).abs() < 1e-6);
+    // red is 0, halfway there with half the strength
+    assert!((material.occlusion(uv, d, d) - 0.5).abs() < 1e-6);
+    let normal = material.normal_texture.as_ref().unwrap();
+    assert_eq!(normal.texture.color_space, ColorSpace::Linear);
+    assert_eq!(material.normal_scale, 0.5);
+
+    // the same image in the same color space is only converted once
+    let base = &material.base_color_texture.as_ref().unwrap().texture;
+    let emissive = &material.emissive_texture.as_ref().unwrap().texture;
+    assert!(Arc::ptr_eq(base, emissive));
+}
+
+#[test]
+fn gltf_material_defaults() {
+    let path = write_gltf_quad("empty_material", "{}", "");
+    let material = &load_gltf_models(&path).unwrap()[0].material;
+    let (uv, d) = (Vec2::ZERO, Vec2::ZERO);
+    assert_eq!(material.base_color(uv, d, d), Vec4::ONE);
+    assert_eq!(material.metallic_roughness(uv, d, d), (1.0, 1.0));
+    assert_eq!(material.occlusion(uv, d, d), 1.0);
+    assert_eq!(material.emissive(uv, d, d), Vec3::ZERO);
+    assert!(material.base_color_texture.is_none());
+    assert!(material.normal_texture.is_none());
+}
+
+#[test]
+fn default_shader_uses_material() {
+    // lit from behind, only the ambient and the emissive color are left
+    let material = Material {
+        base_color_factor: glam::vec4(1.0, 0.0, 0.0, 0.5),
+        emissive_factor: glam::vec3(0.0, 0.5, 0.0),
+        ..Default::default()
+    };
+    let mut shader = DefaultShader::new(&Mat4::IDENTITY, &Mat4::IDENTITY, &material);
+    shader.light_dir = -Vec3::Z;
+    shader.ambient = Vec3::ZERO;
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let quad = plane(Vec2::ONE, 1);
+    let settings = RenderSettings::default();
+    raster_mesh(&quad, &shader, &shader, &settings, &mut target);
+    let center = target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)];
+    assert_eq!(center, glam::vec4(0.0, 0.5, 0.0, 0.5));
+
+    // lit head on, the base color shows
+    shader.light_dir = Vec3::Z;
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    raster_mesh(&quad, &shader, &shader, &settings, &mut target);
+    let center = target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)];
+    assert_eq!(center, glam::vec4(1.0, 0.5, 0.0, 0.5));
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+use ruster::*;
+use std::sync::Arc;
+
+#[test]
+fn plane_tangents() {
+    // u goes along +X and v along +Y
+    let mut mesh = plane(glam::vec2(2.0, 2.0), 2);
+    mesh.generate_tangents();
+    for vertex in mesh.vertices() {
+        assert_eq!(vertex.tangent, glam::vec4(1.0, 0.0, 0.0, 1.0));
+    }
+
+    // mirrored u: the tangent turns around and so does the handedness, the
+    // bitangent keeps following v
+    let mut vertices = plane(glam::vec2(2.0, 2.0), 2).vertices().clone();
+    vertices
+        .iter_mut()
+        .for_each(|vertex| vertex.uv.x = 1.0 - vertex.uv.x);
+    let mut mesh = Mesh::from_vertices(plane(glam::vec2(2.0, 2.0), 2).triangles(), &vertices);
+    mesh.generate_tangents();
+    for vertex in mesh.vertices() {
+        assert_eq!(vertex.tangent, glam::vec4(-1.0, 0.0, 0.0, -1.0));
+        let bitangent = vertex.normal.cross(vertex.tangent.xyz()) * vertex.tangent.w;
+        assert_eq!(bitangent, Vec3::Y);
+    }
+}
+
+// Two quads sharing an edge at x = 0, u grows away from it on both sides: a
+// mirrored half, as used to paint both sides of a model with the same texels
+#[test]
+fn mirrored_uv_seam() {
+    let vertex = |x: f32, y: f32| {
+        let uv = glam::vec2(x.abs(), (y + 1.0) * 0.5);
+        Vertex::new(glam::vec4(x, y, 0.0, 1.0), Vec3::Z, Vec3::ONE, uv)
+    };
+    let vertices = [
+        vertex(-1.0, -1.0),
+        vertex(0.0, -1.0),
+        vertex(-1.0, 1.0),
+        vertex(0.0, 1.0),
+        vertex(1.0, -1.0),
+        vertex(1.0, 1.0),
+    ];
+    let triangles = [
+        glam::uvec3(0, 1, 3),
+        glam::uvec3(0, 3, 2),
+        glam::uvec3(1, 4, 5),
+        glam::uvec3(1, 5, 3),
+    ];
+    let mut mesh = Mesh::from_vertices(&triangles, &vertices);
+    mesh.generate_tangents();
+
+    // the two seam vertices get split, one copy per side
+    assert_eq!(mesh.vertices().len(), 8);
+    for triangle in mesh.triangles() {
+        let corners = mesh.get_vertices_from_triangle(*triangle);
+        let left = corners.iter().map(|v| v.position.x).sum::<f32>() < 0.0;
+        let expected = if left {
+            glam::vec4(-1.0, 0.0, 0.0, -1.0)
+        } else {
+            glam::vec4(1.0, 0.0, 0.0, 1.0)
+        };
+        for corner in corners {
+            assert!(
+                corner.tangent.abs_diff_eq(expected, 1e-5),
+                "{} at {}",
+                corner.tangent,
+                corner.position
+            );
+            // either way the bitangent follows v
+            let bitangent = corner.normal.cross(corner.tangent.xyz()) * corner.tangent.w;
+            assert_close(bitangent, Vec3::Y, 1e-5);
+        }
+    }
+}
+
+#[test]
+fn sphere_tangents_are_orthonormal() {
+    let mut mesh = sphere(16, 8);
+    mesh.generate_tangents();
+    for vertex in mesh.vertices() {
+        let tangent = vertex.tangent.xyz();
+        // the poles have no u direction to follow
+        if tangent == Vec3::ZERO {
+            continue;
+        }
+        assert!((tangent.length() - 1.0).abs() < 1e-4);
+        assert!(tangent.dot(vertex.normal).abs() < 1e-4);
+        assert!(vertex.tangent.w.abs() == 1.0);
+    }
+}
+
+#[test]
+fn no_uvs_no_tangents() {
+    let mut vertices = plane(glam::vec2(2.0, 2.0), 1).vertices().clone();
+    vertices
+        .iter_mut()
+        .for_each(|vertex| vertex.uv = glam::Vec2::ZERO);
+    let mut mesh = Mesh::from_vertices(plane(glam::vec2(2.0, 2.0), 1).triangles(), &vertices);
+    mesh.generate_tangents();
+    for vertex in mesh.vertices() {
+        assert_eq!(vertex.tangent.xyz(), Vec3::ZERO);
+    }
+}
+
+#[test]
+fn perturb() {
+    let tangent = glam::vec4(1.0, 0.0, 0.0, 1.0);
+    assert_close(perturb_normal(Vec3::Z, tangent, Vec3::Z), Vec3::Z, 1e-4);
+    assert_close(perturb_normal(Vec3::Z, tangent, Vec3::X), Vec3::X, 1e-4);
+    assert_close(perturb_normal(Vec3::Z, tangent, Vec3::Y), Vec3::Y, 1e-4);
+    // flipped handedness flips the bitangent only
+    let flipped = glam::vec4(1.0, 0.0, 0.0, -1.0);
+    assert_close(perturb_normal(Vec3::Z, flipped, Vec3::Y), -Vec3::Y, 1e-4);
+    // not normalized nor perpendicular after interpolation
+    let tilted = glam::vec4(2.0, 0.0, 1.0, 1.0);
+    assert_close(
+        perturb_normal(Vec3::Z * 3.0, tilted, Vec3::X),
+        Vec3::X,
+        1e-4,
+    );
+    // without a tangent the normal map can't be applied
+    assert_close(perturb_normal(Vec3::Z, Vec4::ZERO, Vec3::X), Vec3::Z, 1e-4);
+}
+
+// argb, (0.707, 0, 0.707) and (-0.707, 0, 0.707) in tangent space
+const TOWARDS_X: u32 = 0xffda80da;
+const TOWARDS_MINUS_X: u32 = 0xff2580da;
+
+fn normal_map(texel: u32, normal_scale: f32) -> Material {
+    let texture = Texture::from_texels(1, 1, Texels::Argb8(vec![texel]), 4, ColorSpace::Linear);
+    Material {
+        normal_texture: Some(MaterialTexture::new(Arc::new(texture))),
+        normal_scale,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn material_normal() {
+    let sample =
+        |material: &Material| material.normal(glam::Vec2::ZERO, glam::Vec2::ZERO, glam::Vec2::ZERO);
+    assert_eq!(sample(&Material::default()), Vec3::Z);
+    // 0x80 is a hair over the middle
+    let flat = sample(&normal_map(0xff8080ff, 1.0));
+    assert_close(flat, glam::vec3(1.0, 1.0, 255.0).normalize(), 1e-4);
+    // leaning towards +X by 45 degrees
+    let tilted = sample(&normal_map(TOWARDS_X, 1.0));
+    assert!(
+        (tilted.x - tilted.z).abs() < 0.01 && tilted.y.abs() < 0.01,
+        "{:?}",
+        tilted
+    );
+    // normal_scale 0 flattens any normal map
+    assert_close(sample(&normal_map(TOWARDS_X, 0.0)), Vec3::Z, 1e-4);
+}
+
+// lit at 45 degrees, the normal map can turn the plane towards the light or away
+// from it
+#[test]
+fn shading_follows_the_normal_map() {
+    let mut floor = plane(glam::vec2(2.0, 2.0), 1);
+    floor.generate_tangents();
+    let lights = [Light::Directional {
+        direction: glam::vec3(-1.0, 0.0, -1.0).normalize(),
+        color: Vec3::ONE,
+        intensity: 1.0,
+    }];
+    let camera = camera_at(glam::vec3(0.0, 0.0, 3.0));
+    let view_proj = camera.projection() * camera.view();
+    let eye = camera.transform.translation;
+    let render = |material: &Material| {
+        let mut target = RenderTarget::new(WIDTH, HEIGHT);
+        let shader = PbrShader::new(&Mat4::IDENTITY, &view_proj, eye, material, &lights);
+        raster_mesh(
+            &floor,
+            &shader,
+            &shader,
+            &RenderSettings::default(),
+            &mut target,
+        );
+        target.color[HEIGHT / 2 * WIDTH + WIDTH / 2].x
+    };
+    let flat = render(&Material::default());
+    let towards_light = render(&normal_map(TOWARDS_X, 1.0));
+    let away_from_light = render(&normal_map(TOWARDS_MINUS_X, 1.0));
+    assert!(towards_light > flat, "{} {}", towards_light, flat);
+    assert!(away_from_light < flat, "{} {}", away_from_light, flat);
+}
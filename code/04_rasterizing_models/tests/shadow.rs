@@ -0,0 +1,211 @@
+mod common;
+
+use common::*;
+use glam::{Mat4, Vec3};
+use ruster::*;
+use std::sync::Arc;
+
+// 8x8 floor at y = 0 with a 1x1 quad floating over the origin at y = 1
+fn render_scene(shadow_map: &mut ShadowMap) {
+    let to_floor = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    shadow_map.clear();
+    shadow_map.render_mesh(&plane(glam::vec2(8.0, 8.0), 4), &to_floor);
+    let occluder = Mat4::from_translation(Vec3::Y) * to_floor;
+    shadow_map.render_mesh(&plane(glam::vec2(1.0, 1.0), 1), &occluder);
+}
+
+fn sun() -> Light {
+    Light::Directional {
+        direction: -Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 1.0,
+    }
+}
+
+fn settings(pcf_radius: usize) -> ShadowSettings {
+    ShadowSettings {
+        resolution: 64,
+        pcf_radius,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn directional_occluder() {
+    let mut shadow_map = ShadowMap::for_light(&sun(), Vec3::ZERO, 4.0, settings(0)).unwrap();
+    render_scene(&mut shadow_map);
+    assert_eq!(shadow_map.visibility(Vec3::ZERO), 0.0);
+    assert_eq!(shadow_map.visibility(glam::vec3(0.2, 0.0, -0.3)), 0.0);
+    // the floor doesn't shadow itself thanks to the bias
+    assert_eq!(shadow_map.visibility(glam::vec3(2.0, 0.0, 0.0)), 1.0);
+    assert_eq!(shadow_map.visibility(glam::vec3(-1.0, 0.0, 3.0)), 1.0);
+    // the occluder is lit, and so is anything outside the map
+    assert_eq!(shadow_map.visibility(Vec3::Y), 1.0);
+    assert_eq!(shadow_map.visibility(glam::vec3(50.0, 0.0, 0.0)), 1.0);
+}
+
+#[test]
+fn spot_occluder() {
+    let spot = Light::Spot {
+        position: glam::vec3(0.0, 3.0, 0.0),
+        direction: -Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 1.0,
+        range: None,
+        inner_cone_angle: 0.0,
+        outer_cone_angle: 1.0,
+    };
+    let mut shadow_map = ShadowMap::for_light(&spot, Vec3::ZERO, 4.0, settings(0)).unwrap();
+    render_scene(&mut shadow_map);
+    assert_eq!(shadow_map.visibility(Vec3::ZERO), 0.0);
+    // the shadow grows with the distance from the light: 1.5x at the floor
+    assert_eq!(shadow_map.visibility(glam::vec3(0.7, 0.0, 0.0)), 0.0);
+    assert_eq!(shadow_map.visibility(glam::vec3(1.5, 0.0, 0.0)), 1.0);
+}
+
+#[test]
+fn pcf_softens_edges() {
+    // right on the shadow's edge
+    let edge = glam::vec3(0.5, 0.0, 0.0);
+    let mut hard = ShadowMap::for_light(&sun(), Vec3::ZERO, 4.0, settings(0)).unwrap();
+    render_scene(&mut hard);
+    let hard = hard.visibility(edge);
+    assert!(hard == 0.0 || hard == 1.0, "{}", hard);
+
+    let mut soft = ShadowMap::for_light(&sun(), Vec3::ZERO, 4.0, settings(1)).unwrap();
+    render_scene(&mut soft);
+    let visibility = soft.visibility(edge);
+    assert!(visibility > 0.0 && visibility < 1.0, "{}", visibility);
+    // still hard away from it
+    assert_eq!(soft.visibility(Vec3::ZERO), 0.0);
+}
+
+#[test]
+fn depth_bias() {
+    // at an angle to the floor, so depth changes across every texel
+    let light = Light::Directional {
+        direction: glam::vec3(1.0, -3.0, 0.0),
+        color: Vec3::ONE,
+        intensity: 1.0,
+    };
+    let floor = plane(glam::vec2(8.0, 8.0), 4);
+    let to_floor = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let points = (0..20).map(|i| glam::vec3(i as f32 * 0.3 - 3.0, 0.0, (i % 5) as f32 - 2.0));
+    let lit = |depth_bias| {
+        let settings = ShadowSettings {
+            depth_bias,
+            ..settings(0)
+        };
+        let mut shadow_map = ShadowMap::for_light(&light, Vec3::ZERO, 4.0, settings).unwrap();
+        shadow_map.render_mesh(&floor, &to_floor);
+        points
+            .clone()
+            .filter(|point| shadow_map.visibility(*point) == 1.0)
+            .count()
+    };
+    assert!(lit(0.0) < 20);
+    assert_eq!(lit(ShadowSettings::default().depth_bias), 20);
+}
+
+#[test]
+fn point_lights_have_no_shadow_map() {
+    let light = Light::Point {
+        position: Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 1.0,
+        range: None,
+    };
+    assert!(ShadowMap::for_light(&light, Vec3::ZERO, 4.0, settings(1)).is_none());
+}
+
+// PbrShader only darkens the lights that have a shadow map
+#[test]
+fn shader_uses_shadow_maps() {
+    let shadow_map = || {
+        let mut shadow_map = ShadowMap::for_light(&sun(), Vec3::ZERO, 4.0, settings(0)).unwrap();
+        render_scene(&mut shadow_map);
+        Some(shadow_map)
+    };
+    let lights = [sun(), sun()];
+    let material = Material::default();
+    let fragment = |shadow_maps: &[Option<ShadowMap>]| {
+        let shader = PbrShader {
+            shadow_maps,
+            ..PbrShader::new(
+                &Mat4::IDENTITY,
+                &Mat4::IDENTITY,
+                Vec3::Y,
+                &material,
+                &lights,
+            )
+        };
+        let varyings = PbrVaryings {
+            position: Vec3::ZERO,
+            normal: Vec3::Y,
+            tangent: glam::Vec4::ZERO,
+            color: Vec3::ONE,
+            uv: glam::Vec2::ZERO,
+        };
+        let fragment = Fragment {
+            coords: glam::Vec2::ZERO,
+            depth: 0.5,
+            varyings,
+            ddx: varyings,
+            ddy: varyings,
+        };
+        shader.shade_fragment(&fragment).unwrap()
+    };
+    let unshadowed = fragment(&[]);
+    let half = fragment(&[shadow_map(), None]);
+    let ambient = fragment(&[shadow_map(), shadow_map()]);
+    assert!(ambient.x < half.x && half.x < unshadowed.x);
+}
+
+#[test]
+fn alpha_mask_cuts_holes() {
+    // left half opaque, right half cut out
+    let data = vec![to_argb8(255, 255, 255, 255), to_argb8(0, 255, 255, 255)];
+    let texture = MaterialTexture {
+        sampler: Sampler::new(Filter::Nearest),
+        ..MaterialTexture::new(Arc::new(Texture::new(2, 1, data, 4)))
+    };
+    let to_floor = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let mut model = Model {
+        mesh: plane(glam::vec2(2.0, 2.0), 1),
+        material: Material {
+            base_color_texture: Some(texture),
+            ..Default::default()
+        },
+        transform: Mat4::from_translation(Vec3::Y) * to_floor,
+    };
+    let (left, right) = (glam::vec3(-0.5, 0.0, 0.0), glam::vec3(0.5, 0.0, 0.0));
+
+    let mut shadow_map = ShadowMap::for_light(&sun(), Vec3::ZERO, 4.0, settings(0)).unwrap();
+    shadow_map.render_model(&model, &Mat4::IDENTITY);
+    // not masked, the whole quad casts a shadow
+    assert_eq!(shadow_map.visibility(left), 0.0);
+    assert_eq!(shadow_map.visibility(right), 0.0);
+
+    model.material.alpha_mode = AlphaMode::Mask { cutoff: 0.5 };
+    shadow_map.clear();
+    shadow_map.render_model(&model, &Mat4::IDENTITY);
+    assert_eq!(shadow_map.visibility(left), 0.0);
+    assert_eq!(shadow_map.visibility(right), 1.0);
+}
+
+#[test]
+fn spot_out_of_range() {
+    let spot = |range| Light::Spot {
+        position: glam::vec3(0.0, 10.0, 0.0),
+        direction: -Vec3::Y,
+        color: Vec3::ONE,
+        intensity: 1.0,
+        range,
+        inner_cone_angle: 0.3,
+        outer_cone_angle: 0.5,
+    };
+    // the sphere starts 6 away from the light
+    assert!(ShadowMap::for_light(&spot(Some(5.0)), Vec3::ZERO, 4.0, settings(0)).is_none());
+    let shadow_map = ShadowMap::for_light(&spot(Some(8.0)), Vec3::ZERO, 4.0, settings(0));
+    assert!(shadow_map.is_some());
+}
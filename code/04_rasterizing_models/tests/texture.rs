@@ -0,0 +1,455 @@
+mod common;
+
+use common::*;
+use glam::{Vec2, Vec4, Vec4Swizzles};
+use ruster::*;
+use std::cell::RefCell;
+
+fn gray(value: u8) -> u32 {
+    to_argb8(255, value, value, value)
+}
+
+#[test]
+fn mip_chain() {
+    // black and white columns
+    let data = (0..4 * 2)
+        .map(|id| gray(if id & 1 == 0 { 0 } else { 255 }))
+        .collect();
+    let texture = Texture::new(4, 2, data, 4);
+    assert_eq!(texture.level_count(), 3);
+    assert_eq!(texture.level_size(1), (2, 1));
+    assert_eq!(texture.level_size(2), (1, 1));
+    for level in 1..3 {
+        let texel = texture.texel(level, 0, 0);
+        assert!((texel.x - 0.5).abs() <= 1.0 / 255.0, "{}", texel);
+        assert_eq!(texel.w, 1.0);
+    }
+}
+
+// 2x2 texels: black, red, green, blue
+fn quad_texture() -> Texture {
+    let data = vec![
+        to_argb8(255, 0, 0, 0),
+        to_argb8(255, 255, 0, 0),
+        to_argb8(255, 0, 255, 0),
+        to_argb8(255, 0, 0, 255),
+    ];
+    Texture::new(2, 2, data, 4)
+}
+
+#[test]
+fn filters() {
+    let texture = quad_texture();
+    // one texel per pixel, the full size level gets used
+    let (ddx, ddy) = (glam::vec2(0.5, 0.0), glam::vec2(0.0, 0.5));
+    let center = glam::vec2(0.5, 0.5);
+    let average = glam::vec4(0.25, 0.25, 0.25, 1.0);
+
+    let nearest = Sampler::new(Filter::Nearest);
+    let texel = nearest.sample(&texture, glam::vec2(0.7, 0.2), ddx, ddy);
+    assert_eq!(texel, glam::vec4(1.0, 0.0, 0.0, 1.0));
+    // coordinates repeat, negative ones too
+    let texel = nearest.sample(&texture, glam::vec2(-0.3, 1.2), ddx, ddy);
+    assert_eq!(texel, glam::vec4(1.0, 0.0, 0.0, 1.0));
+
+    let bilinear = Sampler::new(Filter::Bilinear);
+    let texel = bilinear.sample(&texture, center, ddx, ddy);
+    assert!(texel.abs_diff_eq(average, 1e-6), "{}", texel);
+    let texel = bilinear.sample(&texture, glam::vec2(0.5, 0.25), ddx, ddy);
+    assert!(texel.abs_diff_eq(glam::vec4(0.5, 0.0, 0.0, 1.0), 1e-6));
+
+    // halfway between the two levels: the texel at the corner of the full size image
+    // and the 1x1 level get blended equally
+    let trilinear = Sampler::new(Filter::Trilinear);
+    let lod = trilinear.level_of_detail(&texture, ddx * 2f32.sqrt(), ddy);
+    assert!((lod - 0.5).abs() < 1e-6);
+    let corner = glam::vec2(0.25, 0.25);
+    let texel = trilinear.sample(&texture, corner, ddx * 2f32.sqrt(), ddy);
+    let expected = (Vec4::W + texture.texel(1, 0, 0)) * 0.5;
+    assert!(texel.abs_diff_eq(expected, 1e-6), "{} {}", texel, expected);
+}
+
+#[test]
+fn level_of_detail() {
+    let texture = checker_texture(64, 4);
+    let sampler = Sampler::default();
+    let texel = 1.0 / 64.0;
+    let lod = |ddx: Vec2, ddy: Vec2| sampler.level_of_detail(&texture, ddx, ddy);
+    assert_eq!(lod(glam::vec2(texel, 0.0), glam::vec2(0.0, texel)), 0.0);
+    assert_eq!(
+        lod(glam::vec2(texel * 4.0, 0.0), glam::vec2(0.0, texel)),
+        2.0
+    );
+    // magnified and minified past the last level
+    assert_eq!(lod(Vec2::ZERO, Vec2::ZERO), 0.0);
+    assert_eq!(lod(Vec2::splat(10.0), Vec2::ZERO), 6.0);
+}
+
+#[test]
+fn quad_derivatives() {
+    // uv goes from 0 to 1 across a 64 pixel wide quad, v grows upwards
+    let derivatives = RefCell::new(Vec::new());
+    let vertex_shader = |vertex: &Vertex| ClipVertex::new(vertex.position, vertex.uv);
+    let fragment_shader = |fragment: &Fragment<Vec2>| {
+        derivatives.borrow_mut().push((fragment.ddx, fragment.ddy));
+        Some(fragment.varyings.extend(0.0).extend(1.0))
+    };
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let quad = plane(Vec2::ONE, 3);
+    let settings = RenderSettings::default();
+    raster_mesh(
+        &quad,
+        &vertex_shader,
+        &fragment_shader,
+        &settings,
+        &mut target,
+    );
+
+    let derivatives = derivatives.into_inner();
+    assert_eq!(derivatives.len(), 64 * 64);
+    let step = 1.0 / 64.0;
+    for (ddx, ddy) in derivatives {
+        assert!(ddx.abs_diff_eq(glam::vec2(step, 0.0), 1e-4), "{}", ddx);
+        assert!(ddy.abs_diff_eq(glam::vec2(0.0, -step), 1e-4), "{}", ddy);
+    }
+
+    // sanity check on the image itself
+    let texel = target.color[coords_to_index(WIDTH / 2, HEIGHT / 2, WIDTH)];
+    assert!(texel.xy().abs_diff_eq(Vec2::splat(0.5), 0.02), "{}", texel);
+}
+
+#[test]
+fn address_modes() {
+    let size = 4;
+    let wrapped = |mode: AddressMode| (-6..10).map(|x| mode.apply(x, size)).collect::<Vec<_>>();
+    assert_eq!(
+        wrapped(AddressMode::Repeat),
+        [2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1]
+    );
+    assert_eq!(
+        wrapped(AddressMode::MirroredRepeat),
+        [2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0, 1]
+    );
+    assert_eq!(
+        wrapped(AddressMode::ClampToEdge),
+        [0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3, 3]
+    );
+}
+
+#[test]
+fn per_axis_address_modes() {
+    let texture = quad_texture();
+    let sampler = Sampler::new(Filter::Nearest)
+        .with_address_mode(AddressMode::ClampToEdge, AddressMode::MirroredRepeat);
+    let (ddx, ddy) = (glam::vec2(0.5, 0.0), glam::vec2(0.0, 0.5));
+    // u clamps to the right column, v mirrors back into the top row
+    let texel = sampler.sample(&texture, glam::vec2(7.0, -0.25), ddx, ddy);
+    assert_eq!(texel, glam::vec4(1.0, 0.0, 0.0, 1.0));
+    let texel = sampler.sample(&texture, glam::vec2(-3.0, 1.75), ddx, ddy);
+    assert_eq!(texel, glam::vec4(0.0, 0.0, 0.0, 1.0));
+
+    // negative coordinates used to saturate to 0 instead of wrapping
+    let id = texture.uv_to_index(-0.25, -0.25);
+    assert_eq!(
+        texture.data.get(id, ColorSpace::Srgb),
+        Vec4::new(0.0, 0.0, 1.0, 1.0)
+    );
+}
+
+#[test]
+fn gltf_sampler() {
+    let material = r#"{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }"#;
+    let sampler = r#"{ "wrapS": 33648, "wrapT": 33071, "minFilter": 9729 }"#;
+    let path = write_gltf_quad("sampler", material, &gltf_texture(sampler));
+    let model = &load_gltf_models(&path).unwrap()[0];
+    let sampler = model.material.base_color_texture.as_ref().unwrap().sampler;
+    assert_eq!(sampler.address_u, AddressMode::MirroredRepeat);
+    assert_eq!(sampler.address_v, AddressMode::ClampToEdge);
+
+    // the spec defaults
+    let path = write_gltf_quad("default_sampler", material, &gltf_texture("{}"));
+    let model = &load_gltf_models(&path).unwrap()[0];
+    let sampler = model.material.base_color_texture.as_ref().unwrap().sampler;
+    assert_eq!(sampler.address_u, AddressMode::Repeat);
+    assert_eq!(sampler.address_v, AddressMode::Repeat);
+    assert_eq!(sampler.min_filter, TexelFilter::Linear);
+    assert_eq!(sampler.mag_filter, TexelFilter::Linear);
+    assert_eq!(sampler.mipmap, MipmapMode::Linear);
+}
+
+#[test]
+fn gltf_sampler_filters() {
+    use MipmapMode as Mip;
+    use TexelFilter::{Linear, Nearest};
+    let material = r#"{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }"#;
+    let min_filters = [
+        (9728, Nearest, Mip::None),
+        (9729, Linear, Mip::None),
+        (9984, Nearest, Mip::Nearest),
+        (9985, Linear, Mip::Nearest),
+        (9986, Nearest, Mip::Linear),
+        (9987, Linear, Mip::Linear),
+    ];
+    for (min_filter, min, mipmap) in min_filters {
+        for (mag_filter, mag) in [(9728, Nearest), (9729, Linear)] {
+            let json = format!(
+                r#"{{ "minFilter": {}, "magFilter": {} }}"#,
+                min_filter, mag_filter
+            );
+            let name = format!("sampler_{}_{}", min_filter, mag_filter);
+            let path = write_gltf_quad(&name, material, &gltf_texture(&json));
+            let model = &load_gltf_models(&path).unwrap()[0];
+            let sampler = model.material.base_color_texture.as_ref().unwrap().sampler;
+            assert_eq!(
+                (sampler.min_filter, sampler.mag_filter, sampler.mipmap),
+                (min, mag, mipmap),
+                "{}",
+                json
+            );
+        }
+    }
+}
+
+#[test]
+fn mag_and_min_filters() {
+    let texture = quad_texture();
+    let sampler = |min_filter, mag_filter, mipmap| Sampler {
+        min_filter,
+        mag_filter,
+        mipmap,
+        ..Default::default()
+    };
+    let center = glam::vec2(0.5, 0.5);
+    let average = glam::vec4(0.25, 0.25, 0.25, 1.0);
+    let corner = Vec4::W;
+    let smallest = texture.texel(1, 0, 0);
+    let magnified = (glam::vec2(0.25, 0.0), glam::vec2(0.0, 0.25));
+    let minified = (glam::vec2(1.0, 0.0), glam::vec2(0.0, 1.0));
+
+    // magnified, only the mag filter matters
+    let nearest_mag = sampler(
+        TexelFilter::Linear,
+        TexelFilter::Nearest,
+        MipmapMode::Linear,
+    );
+    let texel = nearest_mag.sample(&texture, center, magnified.0, magnified.1);
+    assert_eq!(texel, glam::vec4(0.0, 0.0, 1.0, 1.0));
+    let linear_mag = sampler(TexelFilter::Nearest, TexelFilter::Linear, MipmapMode::None);
+    let texel = linear_mag.sample(&texture, center, magnified.0, magnified.1);
+    assert!(texel.abs_diff_eq(average, 1e-6), "{}", texel);
+
+    // minified to the 1x1 level, unless there are no mips to use
+    let base_only = sampler(TexelFilter::Nearest, TexelFilter::Linear, MipmapMode::None);
+    let texel = base_only.sample(&texture, glam::vec2(0.25, 0.25), minified.0, minified.1);
+    assert_eq!(texel, corner);
+    let mipmapped = sampler(
+        TexelFilter::Nearest,
+        TexelFilter::Linear,
+        MipmapMode::Nearest,
+    );
+    let texel = mipmapped.sample(&texture, glam::vec2(0.25, 0.25), minified.0, minified.1);
+    assert_eq!(texel, smallest);
+
+    // NEAREST_MIPMAP_LINEAR: nearest texel of each level, the levels blended
+    let nearest_linear = sampler(
+        TexelFilter::Nearest,
+        TexelFilter::Nearest,
+        MipmapMode::Linear,
+    );
+    let (ddx, ddy) = (glam::vec2(0.5, 0.0) * 2f32.sqrt(), glam::vec2(0.0, 0.5));
+    let texel = nearest_linear.sample(&texture, glam::vec2(0.3, 0.3), ddx, ddy);
+    let expected = (corner + smallest) * 0.5;
+    assert!(texel.abs_diff_eq(expected, 1e-6), "{} {}", texel, expected);
+    // where trilinear would have blended the 2x2 texels as well
+    let trilinear = Sampler::new(Filter::Trilinear);
+    let texel = trilinear.sample(&texture, glam::vec2(0.3, 0.3), ddx, ddy);
+    assert!(!texel.abs_diff_eq(expected, 1e-3), "{}", texel);
+}
+
+#[test]
+fn anisotropic_filtering() {
+    // one texel high black and white stripes, constant along u
+    let data = (0..16 * 16)
+        .map(|id| gray(if (id / 16) & 1 == 0 { 0 } else { 255 }))
+        .collect();
+    let texture = Texture::new(16, 16, data, 4);
+    // 8 texels along u, 1 along v: like a floor seen at a grazing angle
+    let (ddx, ddy) = (glam::vec2(0.5, 0.0), glam::vec2(0.0, 1.0 / 16.0));
+    let white_row = glam::vec2(0.3, 1.5 / 16.0);
+
+    let isotropic = Sampler::default();
+    assert_eq!(isotropic.anisotropy(&texture, ddx, ddy), (ddx, 1));
+    let texel = isotropic.sample(&texture, white_row, ddx, ddy);
+    assert!(
+        (texel.x - 0.5).abs() < 0.02,
+        "stripes blurred away: {}",
+        texel
+    );
+
+    let anisotropic = Sampler::default().with_max_anisotropy(16.0);
+    assert_eq!(anisotropic.anisotropy(&texture, ddx, ddy), (ddx, 8));
+    let texel = anisotropic.sample(&texture, white_row, ddx, ddy);
+    assert!(
+        texel.abs_diff_eq(Vec4::ONE, 1e-6),
+        "stripes kept: {}",
+        texel
+    );
+
+    // capped: 4 samples, each one covering 2 texels
+    let capped = Sampler::default().with_max_anisotropy(4.0);
+    assert_eq!(capped.anisotropy(&texture, ddx, ddy), (ddx, 4));
+    let texel = capped.sample(&texture, white_row, ddx, ddy);
+    assert!(texel.x < 1.0 && texel.x > 0.5, "{}", texel);
+
+    // set directly instead of through with_max_anisotropy, used to panic in clamp
+    for max_anisotropy in [0.0, -4.0, f32::NAN] {
+        let sampler = Sampler {
+            max_anisotropy,
+            ..Sampler::default()
+        };
+        assert_eq!(sampler.anisotropy(&texture, ddx, ddy), (ddx, 1));
+    }
+
+    // 64 texels by 1, still no more than 16 samples
+    let (ddx, ddy) = (glam::vec2(4.0, 0.0), glam::vec2(0.0, 1.0 / 16.0));
+    for max_anisotropy in [f32::INFINITY, 1e9] {
+        let sampler = Sampler::default().with_max_anisotropy(max_anisotropy);
+        assert_eq!(sampler.max_anisotropy, MAX_ANISOTROPY);
+        let sampler = Sampler {
+            max_anisotropy,
+            ..Sampler::default()
+        };
+        assert_eq!(sampler.anisotropy(&texture, ddx, ddy), (ddx, 16));
+    }
+}
+
+fn write_png(name: &str, color: png::ColorType, width: u32, pixels: &[u8]) -> std::path::PathBuf {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("textures");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let samples = color.samples() as u32;
+    let height = pixels.len() as u32 / (width * samples);
+    let file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+    path
+}
+
+#[test]
+fn load_channel_counts() {
+    let cases = [
+        (png::ColorType::Grayscale, vec![10, 200], 1),
+        (png::ColorType::GrayscaleAlpha, vec![10, 255, 200, 100], 2),
+        (png::ColorType::Rgb, vec![10, 10, 10, 200, 200, 200], 3),
+        (
+            png::ColorType::Rgba,
+            vec![10, 10, 10, 255, 200, 200, 200, 100],
+            4,
+        ),
+    ];
+    for (color, pixels, depth) in cases {
+        let path = write_png(&format!("{:?}.png", color), color, 2, &pixels);
+        let texture = Texture::load(&path).unwrap();
+        assert_eq!(
+            (texture.width, texture.height, texture.depth),
+            (2, 1, depth)
+        );
+        let alpha = if depth == 2 || depth == 4 { 100 } else { 255 };
+        assert_eq!(
+            texture.data,
+            Texels::Argb8(vec![
+                to_argb8(255, 10, 10, 10),
+                to_argb8(alpha, 200, 200, 200)
+            ]),
+            "{:?}",
+            color
+        );
+    }
+}
+
+#[test]
+fn load_errors() {
+    let missing = Texture::load(std::path::Path::new("does/not/exist.png"));
+    assert!(matches!(missing, Err(TextureError::Io(_))));
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("textures");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("garbage.png");
+    std::fs::write(&path, b"not an image").unwrap();
+    let garbage = Texture::load(&path);
+    assert!(matches!(garbage, Err(TextureError::Decode(_))));
+}
+
+#[test]
+fn scene_load_errors() {
+    let missing = load_gltf_models(std::path::Path::new("does/not/exist.gltf"));
+    assert!(matches!(missing, Err(SceneError::Gltf(_))));
+
+    // the image isn't a png, gltf::import already fails decoding it
+    let broken = r#",
+  "textures": [{ "source": 0 }],
+  "images": [{ "uri": "data:image/png;base64,AAAA" }]"#;
+    let material = r#"{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }"#;
+    let path = write_gltf_quad("broken_image", material, broken);
+    assert!(matches!(load_gltf_models(&path), Err(SceneError::Gltf(_))));
+}
+
+#[test]
+fn srgb_decode() {
+    let data = vec![to_argb8(128, 188, 188, 188); 4];
+    let srgb = Texture::new(2, 2, data.clone(), 4);
+    let texel = srgb.texel(0, 0, 0);
+    assert!(
+        texel.abs_diff_eq(glam::vec4(0.5, 0.5, 0.5, 128.0 / 255.0), 4e-3),
+        "{}",
+        texel
+    );
+
+    let linear = Texture::from_texels(2, 2, Texels::Argb8(data), 4, ColorSpace::Linear);
+    assert_eq!(
+        linear.texel(0, 0, 0),
+        Vec4::splat(188.0 / 255.0).truncate().extend(128.0 / 255.0)
+    );
+
+    // black and white average to half the light, not to half the code values
+    let data = (0..4)
+        .map(|id| gray(if id & 1 == 0 { 0 } else { 255 }))
+        .collect();
+    let texture = Texture::new(2, 2, data, 4);
+    assert_eq!(texture.mips[0].data, Texels::Argb8(vec![gray(188)]));
+}
+
+#[test]
+fn load_hdr() {
+    // flat (not run length encoded) RGBE: 2.0 is 128 * 2^(130 - 136)
+    let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+    bytes.extend_from_slice(&[128, 128, 128, 130, 128, 64, 0, 128]);
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("textures");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sky.hdr");
+    std::fs::write(&path, bytes).unwrap();
+
+    let texture = Texture::load(&path).unwrap();
+    assert_eq!((texture.width, texture.height, texture.depth), (2, 1, 3));
+    assert_eq!(
+        texture.data,
+        Texels::Float(vec![
+            Vec4::new(2.0, 2.0, 2.0, 1.0),
+            Vec4::new(0.5, 0.25, 0.0, 1.0)
+        ])
+    );
+    // no sRGB decode on float texels, and nothing gets clamped
+    let sampler = Sampler::new(Filter::Nearest);
+    let texel = sampler.sample(&texture, glam::vec2(0.25, 0.5), Vec2::ZERO, Vec2::ZERO);
+    assert_eq!(texel, Vec4::new(2.0, 2.0, 2.0, 1.0));
+    assert_eq!(
+        texture.mips[0].data,
+        Texels::Float(vec![Vec4::new(1.25, 1.125, 1.0, 1.0)])
+    );
+}
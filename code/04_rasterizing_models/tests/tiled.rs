@@ -0,0 +1,76 @@
+// The tiled renderer has to produce exactly the same pixels as raster_mesh
+mod common;
+
+use common::*;
+use glam::Mat4;
+use ruster::*;
+use std::sync::Arc;
+
+// workers: 0 for raster_mesh, the tiled path always gets taken from 2 on
+fn render(workers: usize, settings: &RenderSettings) -> RenderTarget {
+    // not a multiple of TILE_SIZE on purpose, so border tiles are partial
+    let mut target = RenderTarget::new(3 * TILE_SIZE + 17, 2 * TILE_SIZE + 5);
+    let material = Material::from_texture(Arc::new(checker_texture(64, 4)));
+    let mut camera = camera_at(glam::vec3(0.0, 1.0, 0.0));
+    camera.aspect_ratio = target.width as f32 / target.height as f32;
+    camera.transform.rotation = glam::Quat::from_rotation_x(-0.3);
+    let view_proj = camera.projection() * camera.view();
+
+    let floor = plane(glam::vec2(40.0, 40.0), 16);
+    let floor_model = Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+    let floor_shader = DefaultShader::new(&floor_model, &(view_proj * floor_model), &material);
+    let cube_model =
+        Mat4::from_translation(glam::vec3(0.3, 0.5, -3.0)) * Mat4::from_rotation_y(0.5);
+    let untextured = Material::default();
+    let cube_shader = DefaultShader::new(&cube_model, &(view_proj * cube_model), &untextured);
+
+    if workers > 0 {
+        raster_mesh_tiled_with_workers(
+            &floor,
+            &floor_shader,
+            &floor_shader,
+            settings,
+            &mut target,
+            workers,
+        );
+        raster_mesh_tiled_with_workers(
+            &cube(),
+            &cube_shader,
+            &cube_shader,
+            settings,
+            &mut target,
+            workers,
+        );
+    } else {
+        raster_mesh(&floor, &floor_shader, &floor_shader, settings, &mut target);
+        raster_mesh(&cube(), &cube_shader, &cube_shader, settings, &mut target);
+    }
+    target
+}
+
+#[test]
+fn tiled_matches_single_threaded() {
+    for settings in [
+        RenderSettings::default(),
+        RenderSettings {
+            clip_debug: true,
+            ..Default::default()
+        },
+    ] {
+        let single = render(0, &settings);
+        // more workers than cores is fine, they just take turns
+        for workers in [1, 2, 4] {
+            let tiled = render(workers, &settings);
+            assert!(
+                single.color == tiled.color,
+                "color differs, {} workers",
+                workers
+            );
+            assert!(
+                single.depth == tiled.depth,
+                "depth differs, {} workers",
+                workers
+            );
+        }
+    }
+}
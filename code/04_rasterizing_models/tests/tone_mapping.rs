@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use glam::{Vec3, Vec4};
+use ruster::post_process::{average_luminance, luminance};
+use ruster::*;
+
+const MAPPERS: [ToneMapper; 4] = [
+    ToneMapper::Clamp,
+    ToneMapper::Reinhard,
+    ToneMapper::AcesFilmic,
+    ToneMapper::Uchimura,
+];
+
+#[test]
+fn curves() {
+    for mapper in MAPPERS {
+        let map = |x: f32| mapper.apply(Vec3::splat(x)).x;
+        assert!(map(0.0).abs() < 1e-3, "{:?}", mapper);
+        let mut last = 0.0;
+        for i in 1..200 {
+            let mapped = map(i as f32 * 0.1);
+            assert!(
+                mapped >= last,
+                "{:?} goes down at {}",
+                mapper,
+                i as f32 * 0.1
+            );
+            assert!(mapped <= 1.0, "{:?}", mapper);
+            last = mapped;
+        }
+    }
+    assert_eq!(
+        ToneMapper::Clamp.apply(glam::vec3(0.3, 4.0, -1.0)),
+        glam::vec3(0.3, 1.0, 0.0)
+    );
+    assert_eq!(ToneMapper::Reinhard.apply(Vec3::ONE), Vec3::splat(0.5));
+    assert!((ToneMapper::AcesFilmic.apply(Vec3::ONE).x - 0.8).abs() < 0.01);
+    // the middle of Uchimura's curve is left as it is
+    let mid = ToneMapper::Uchimura.apply(Vec3::splat(0.4)).x;
+    assert!((mid - 0.4).abs() < 1e-6, "{}", mid);
+    assert!(ToneMapper::Uchimura.apply(Vec3::splat(100.0)).x > 0.99);
+}
+
+fn filled(color: Vec4) -> RenderTarget {
+    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    target.clear(color);
+    target
+}
+
+#[test]
+fn exposure() {
+    let post_process = PostProcess {
+        exposure: 1.0,
+        ..Default::default()
+    };
+    let mut target = filled(glam::vec4(0.25, 0.5, 2.0, 0.5));
+    post_process.apply(&mut target);
+    // one stop doubles the light, then gets clamped. Alpha is untouched
+    assert_eq!(target.color[0], glam::vec4(0.5, 1.0, 1.0, 0.5));
+}
+
+#[test]
+fn auto_exposure() {
+    let bright = glam::vec4(8.0, 8.0, 8.0, 1.0);
+    let mut target = filled(bright);
+    assert!((average_luminance(&target) - 8.0).abs() < 1e-3);
+
+    let mut post_process = PostProcess {
+        auto_exposure: true,
+        ..Default::default()
+    };
+    post_process.apply(&mut target);
+    let gray = luminance(target.color[0].truncate());
+    assert!((gray - 0.18).abs() < 1e-3, "{}", gray);
+
+    // exposure still shifts the result
+    let mut target = filled(bright);
+    post_process.exposure = 2.0;
+    post_process.apply(&mut target);
+    let gray = luminance(target.color[0].truncate());
+    assert!((gray - 0.72).abs() < 1e-3, "{}", gray);
+
+    // a handful of very bright pixels barely moves the geometric mean
+    let mut target = filled(glam::vec4(0.5, 0.5, 0.5, 1.0));
+    for id in 0..16 {
+        target.color[id] = Vec4::splat(1000.0);
+    }
+    assert!(average_luminance(&target) < 0.55);
+}